@@ -0,0 +1,161 @@
+use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
+use near_sdk::json_types::U128;
+use near_sdk::serde::{Deserialize, Serialize};
+use near_sdk::{env, near_bindgen, NearSchema, Timestamp};
+
+use crate::{HTLCNear, HTLCNearExt};
+
+/// yoctoNEAR per NEAR. Auction rates are destination base units per whole
+/// NEAR, so 3 USDC per NEAR is a rate of 3_000_000.
+pub const RATE_SCALE: u128 = 1_000_000_000_000_000_000_000_000;
+
+/// Most points a piecewise auction curve may have.
+pub const MAX_CURVE_POINTS: usize = 16;
+
+/// Rate a curve reaches `offset` milliseconds after the auction starts.
+#[derive(
+    BorshDeserialize, BorshSerialize, Serialize, Deserialize, NearSchema, Clone, PartialEq, Debug,
+)]
+#[serde(crate = "near_sdk::serde")]
+#[abi(json)]
+pub struct AuctionPoint {
+    pub offset: u64,
+    pub rate: U128,
+}
+
+/// Fusion+ Dutch auction of a partial fill swap. Rates are destination base
+/// units per NEAR (`RATE_SCALE` yoctoNEAR). The rate falls from
+/// `start_rate` at `start_time` to `end_rate` after `duration` milliseconds,
+/// linearly or through the points of `curve`, and stays there.
+#[derive(
+    BorshDeserialize, BorshSerialize, Serialize, Deserialize, NearSchema, Clone, PartialEq, Debug,
+)]
+#[serde(crate = "near_sdk::serde")]
+#[abi(json)]
+pub struct DutchAuction {
+    pub start_rate: U128,
+    pub end_rate: U128,
+    pub start_time: Timestamp,
+    pub duration: u64,
+    #[serde(default)]
+    pub curve: Vec<AuctionPoint>,
+}
+
+impl DutchAuction {
+    pub fn assert_valid(&self) {
+        assert!(self.duration > 0, "Auction duration must be greater than 0");
+        assert!(
+            self.curve.len() <= MAX_CURVE_POINTS,
+            "Too many auction curve points"
+        );
+        let mut last_offset = 0;
+        let mut last_rate = self.start_rate.0;
+        for point in &self.curve {
+            assert!(
+                point.offset > last_offset && point.offset < self.duration,
+                "Auction curve offsets must increase within the duration"
+            );
+            assert!(point.rate.0 <= last_rate, "Auction rates must not increase");
+            last_offset = point.offset;
+            last_rate = point.rate.0;
+        }
+        assert!(
+            self.end_rate.0 <= last_rate,
+            "Auction rates must not increase"
+        );
+    }
+
+    /// Rate at `now`, in milliseconds.
+    pub fn rate_at(&self, now: Timestamp) -> U128 {
+        let elapsed = now.saturating_sub(self.start_time).min(self.duration);
+        let points = std::iter::once((0, self.start_rate.0))
+            .chain(self.curve.iter().map(|point| (point.offset, point.rate.0)))
+            .chain(std::iter::once((self.duration, self.end_rate.0)))
+            .collect::<Vec<_>>();
+
+        let segment = points
+            .windows(2)
+            .find(|segment| elapsed <= segment[1].0)
+            .expect("Elapsed time within the duration");
+        let ((from_offset, from_rate), (to_offset, to_rate)) = (segment[0], segment[1]);
+        U128(interpolate(
+            from_rate,
+            to_rate,
+            u128::from(elapsed - from_offset),
+            u128::from(to_offset - from_offset),
+        ))
+    }
+
+    /// Destination amount owed for `amount` yoctoNEAR at `rate`.
+    pub fn destination_amount(amount: U128, rate: U128) -> U128 {
+        U128(
+            mul_div(amount.0, rate.0, RATE_SCALE)
+                .unwrap_or_else(|| env::panic_str("Destination amount overflow")),
+        )
+    }
+}
+
+fn interpolate(from: u128, to: u128, elapsed: u128, span: u128) -> u128 {
+    // `elapsed <= span`, so the result never exceeds `delta`
+    let mul = |delta: u128| mul_div(delta, elapsed, span).expect("Bounded by the rate delta");
+    if to >= from {
+        from + mul(to - from)
+    } else {
+        from - mul(from - to)
+    }
+}
+
+/// `a * b / d` rounded down, with the product held in 256 bits. `None` if
+/// the quotient doesn't fit in a `u128`.
+fn mul_div(a: u128, b: u128, d: u128) -> Option<u128> {
+    let (hi, lo) = widening_mul(a, b);
+    if hi >= d {
+        return None;
+    }
+    // Long division of `hi:lo` by `d`, one bit of `lo` at a time. `rem`
+    // stays below `d`; a bit shifted out of it means it passed `d`.
+    let mut rem = hi;
+    let mut quotient = 0;
+    for bit in (0..128).rev() {
+        let carry = rem >> 127;
+        rem = (rem << 1) | ((lo >> bit) & 1);
+        quotient <<= 1;
+        if carry == 1 || rem >= d {
+            rem = rem.wrapping_sub(d);
+            quotient |= 1;
+        }
+    }
+    Some(quotient)
+}
+
+/// Full product of `a` and `b` as `(high, low)` 128-bit halves.
+fn widening_mul(a: u128, b: u128) -> (u128, u128) {
+    const MASK: u128 = u64::MAX as u128;
+    let (a_hi, a_lo) = (a >> 64, a & MASK);
+    let (b_hi, b_lo) = (b >> 64, b & MASK);
+    let lo_lo = a_lo * b_lo;
+    let hi_lo = a_hi * b_lo;
+    let lo_hi = a_lo * b_hi;
+    let mid = (lo_lo >> 64) + (hi_lo & MASK) + (lo_hi & MASK);
+    let lo = (lo_lo & MASK) | (mid << 64);
+    let hi = a_hi * b_hi + (hi_lo >> 64) + (lo_hi >> 64) + (mid >> 64);
+    (hi, lo)
+}
+
+#[near_bindgen]
+impl HTLCNear {
+    /// Current auction rate of a partial fill swap, `None` if it has no
+    /// auction
+    pub fn get_current_rate(&self, swap_id: String) -> Option<U128> {
+        self.partial_fill_swaps
+            .get(&swap_id)
+            .and_then(|swap| swap.auction)
+            .map(|auction| auction.rate_at(env::block_timestamp_ms()))
+    }
+
+    /// Minimum destination amount a fill of `fill_amount` would owe now
+    pub fn get_fill_price(&self, swap_id: String, fill_amount: U128) -> Option<U128> {
+        self.get_current_rate(swap_id)
+            .map(|rate| DutchAuction::destination_amount(fill_amount, rate))
+    }
+}
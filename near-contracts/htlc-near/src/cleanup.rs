@@ -0,0 +1,247 @@
+use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
+use near_sdk::serde::{Deserialize, Serialize};
+use near_sdk::{env, near_bindgen, AccountId, NearSchema, StorageUsage, Timestamp};
+
+use crate::migration::RecordMap;
+use crate::{
+    EscrowKind, EscrowStatus, HTLCNear, HTLCNearExt, HtlcEvent, PartialFillSwap, SwapStatus,
+};
+
+/// Most escrows `cleanup_settled_escrows` accepts per call.
+pub const MAX_CLEANUP_BATCH: usize = 50;
+
+/// Default time a settled escrow stays readable after its timelock.
+pub const DEFAULT_RETENTION_PERIOD_MS: u64 = 30 * 24 * 60 * 60 * 1000;
+
+/// Compact record kept for an escrow whose full record was removed.
+#[derive(BorshDeserialize, BorshSerialize, Clone)]
+pub struct ArchivedEscrow {
+    pub status: EscrowStatus,
+    pub hashlock: Vec<u8>,
+}
+
+#[derive(Serialize, Deserialize, NearSchema, Clone)]
+#[serde(crate = "near_sdk::serde")]
+#[abi(json)]
+pub struct ArchivedEscrowView {
+    pub id: String,
+    pub status: EscrowStatus,
+    pub hashlock: String,
+}
+
+/// The fields of a settled HTLC, cross-chain HTLC, NFT escrow or partial fill that
+/// cleanup needs, whatever collection it lives in.
+struct SettledEscrow {
+    kind: EscrowKind,
+    sender: AccountId,
+    receiver: AccountId,
+    hashlock: Vec<u8>,
+    timelock: Timestamp,
+    status: EscrowStatus,
+    parent_swap_id: Option<String>,
+    // Account charged for the escrow's storage on creation
+    storage_payer: AccountId,
+}
+
+impl HTLCNear {
+    fn settled_escrow(&self, id: &String) -> Option<SettledEscrow> {
+        let escrow = if let Some(contract) = self.contracts.record(id) {
+            SettledEscrow {
+                kind: EscrowKind::Htlc,
+                status: contract.status.at(contract.timelock),
+                storage_payer: contract.sender.clone(),
+                sender: contract.sender,
+                receiver: contract.receiver,
+                hashlock: contract.hashlock,
+                timelock: contract.timelock,
+                parent_swap_id: None,
+            }
+        } else if let Some(contract) = self.cross_chain_contracts.record(id) {
+            SettledEscrow {
+                kind: EscrowKind::CrossChain,
+                status: contract.status.at(contract.timelock),
+                storage_payer: contract.sender.clone(),
+                sender: contract.sender,
+                receiver: contract.receiver,
+                hashlock: contract.hashlock,
+                timelock: contract.timelock,
+                parent_swap_id: None,
+            }
+        } else if let Some(escrow) = self.nft_escrows.get(id) {
+            // A token whose transfer failed is still held by the contract
+            if escrow.transfer_failed {
+                return None;
+            }
+            SettledEscrow {
+                kind: EscrowKind::Nft,
+                status: escrow.status.at(escrow.timelock),
+                storage_payer: escrow.sender.clone(),
+                sender: escrow.sender,
+                receiver: escrow.receiver,
+                hashlock: escrow.hashlock,
+                timelock: escrow.timelock,
+                parent_swap_id: None,
+            }
+        } else if let Some(fill) = self.partial_fills.record(id) {
+            SettledEscrow {
+                kind: EscrowKind::PartialFill,
+                status: fill.status.at(fill.timelock),
+                // Fills are paid for by the resolver claiming them
+                storage_payer: fill.receiver.clone(),
+                sender: fill.sender,
+                receiver: fill.receiver,
+                hashlock: fill.hashlock,
+                timelock: fill.timelock,
+                parent_swap_id: Some(fill.parent_swap_id),
+            }
+        } else {
+            return None;
+        };
+
+        matches!(
+            escrow.status,
+            EscrowStatus::Withdrawn | EscrowStatus::Refunded
+        )
+        .then_some(escrow)
+    }
+
+    /// Remove one settled escrow past its retention period, keep an archive
+    /// record and credit the freed storage to the account that paid for it.
+    fn cleanup_escrow(&mut self, id: String) -> bool {
+        if let Some(swap) = self.partial_fill_swaps.record(&id) {
+            return self.cleanup_swap(id, swap);
+        }
+        let Some(escrow) = self.settled_escrow(&id) else {
+            return false;
+        };
+        if env::block_timestamp_ms() <= escrow.timelock.saturating_add(self.retention_period_ms) {
+            return false;
+        }
+
+        let initial_storage = env::storage_usage();
+        match escrow.kind {
+            EscrowKind::Htlc => {
+                self.contracts.remove(&id);
+            }
+            EscrowKind::CrossChain => {
+                self.cross_chain_contracts.remove(&id);
+            }
+            EscrowKind::Nft => {
+                self.nft_escrows.remove(&id);
+            }
+            EscrowKind::PartialFill => {
+                self.partial_fills.remove(&id);
+            }
+            EscrowKind::PartialFillSwap => unreachable!("Swaps are never settled escrows"),
+        }
+        self.unindex_escrow(&id, &escrow.sender, &escrow.receiver);
+        if let Some(swap_id) = escrow.parent_swap_id {
+            if let Some(mut swap_fills) = self.fills_by_swap.get(&swap_id) {
+                swap_fills.remove(&id);
+                self.fills_by_swap.insert(&swap_id, &swap_fills);
+            }
+        }
+        self.archived_escrows.insert(
+            &id,
+            &ArchivedEscrow {
+                status: escrow.status,
+                hashlock: escrow.hashlock.clone(),
+            },
+        );
+        let freed_bytes: StorageUsage = initial_storage.saturating_sub(env::storage_usage());
+        self.release_storage(&escrow.storage_payer, freed_bytes);
+
+        HtlcEvent::EscrowArchived {
+            id,
+            sender: escrow.sender,
+            status: escrow.status,
+            hashlock: hex::encode(&escrow.hashlock),
+            freed_bytes,
+        }
+        .emit();
+
+        true
+    }
+
+    /// Remove a settled or cancelled swap without open fills past its
+    /// retention period, along with its fill set, and credit the freed
+    /// storage to the maker. Fills still listed under the swap have to be
+    /// cleaned up first.
+    fn cleanup_swap(&mut self, swap_id: String, swap: PartialFillSwap) -> bool {
+        if !matches!(swap.status, SwapStatus::Settled | SwapStatus::Cancelled)
+            || swap.open_fill_count > 0
+        {
+            return false;
+        }
+        if env::block_timestamp_ms() <= swap.timelock.saturating_add(self.retention_period_ms) {
+            return false;
+        }
+        if self
+            .fills_by_swap
+            .get(&swap_id)
+            .is_some_and(|fill_ids| !fill_ids.is_empty())
+        {
+            return false;
+        }
+
+        let initial_storage = env::storage_usage();
+        self.partial_fill_swaps.remove(&swap_id);
+        self.fills_by_swap.remove(&swap_id);
+        let freed_bytes: StorageUsage = initial_storage.saturating_sub(env::storage_usage());
+        self.release_storage(&swap.sender, freed_bytes);
+
+        HtlcEvent::PartialFillSwapArchived {
+            swap_id,
+            sender: swap.sender,
+            status: swap.status,
+            freed_bytes,
+        }
+        .emit();
+
+        true
+    }
+}
+
+#[near_bindgen]
+impl HTLCNear {
+    /// Remove settled (withdrawn or refunded) escrows whose timelock passed
+    /// more than the retention period ago. Callable by anyone with up to
+    /// `MAX_CLEANUP_BATCH` IDs; IDs that are unknown, still open or inside
+    /// the retention period are skipped. Settled or cancelled partial fill
+    /// swaps are removed once their fills are, so list fills before their
+    /// swap. Returns how many were removed. Not available while record
+    /// migration runs, as removals move the records it walks.
+    pub fn cleanup_settled_escrows(&mut self, ids: Vec<String>) -> u32 {
+        self.assert_migrated();
+        assert!(
+            ids.len() <= MAX_CLEANUP_BATCH,
+            "Too many escrows in one cleanup batch"
+        );
+        let mut cleaned = 0;
+        for id in ids {
+            if self.cleanup_escrow(id) {
+                cleaned += 1;
+            }
+        }
+        cleaned
+    }
+
+    pub fn get_archived_escrow(&self, id: String) -> Option<ArchivedEscrowView> {
+        self.archived_escrows
+            .get(&id)
+            .map(|archived| ArchivedEscrowView {
+                id,
+                status: archived.status,
+                hashlock: hex::encode(&archived.hashlock),
+            })
+    }
+
+    pub fn set_retention_period(&mut self, retention_period_ms: u64) {
+        self.assert_owner();
+        self.retention_period_ms = retention_period_ms;
+    }
+
+    pub fn get_retention_period(&self) -> u64 {
+        self.retention_period_ms
+    }
+}
@@ -0,0 +1,361 @@
+use near_sdk::json_types::U128;
+use near_sdk::{near_bindgen, AccountId, StorageUsage, Timestamp};
+
+use crate::{
+    DutchAuction, EscrowStatus, HashAlgorithm, MerkleSecrets, Role, SwapStatus, Timelocks,
+};
+
+/// NEP-297 events emitted by the HTLC contract.
+///
+/// Every log line has the form `EVENT_JSON:{"standard":"htlc-near","version":..,"event":..,"data":{..}}`.
+/// Hashlocks and preimages are hex encoded, amounts are yoctoNEAR strings, or
+/// token units where a `token` contract is given.
+#[near_bindgen(event_json(standard = "htlc-near"))]
+pub enum HtlcEvent {
+    #[event_version("1.0.0")]
+    HtlcCreated {
+        id: String,
+        sender: AccountId,
+        receiver: AccountId,
+        amount: U128,
+        hashlock: String,
+        timelock: Timestamp,
+        eth_address: String,
+        token: Option<AccountId>,
+        hash_algorithm: HashAlgorithm,
+    },
+
+    #[event_version("1.0.0")]
+    HtlcWithdrawn {
+        id: String,
+        sender: AccountId,
+        receiver: AccountId,
+        amount: U128,
+        fee: U128,
+        status: EscrowStatus,
+        hashlock: String,
+        preimage: String,
+        eth_address: String,
+    },
+
+    #[event_version("1.0.0")]
+    HtlcRefunded {
+        id: String,
+        sender: AccountId,
+        receiver: AccountId,
+        amount: U128,
+        status: EscrowStatus,
+        hashlock: String,
+        timelock: Timestamp,
+        eth_address: String,
+    },
+
+    #[event_version("1.0.0")]
+    CrossChainHtlcCreated {
+        id: String,
+        sender: AccountId,
+        receiver: AccountId,
+        amount: U128,
+        hashlock: String,
+        timelock: Timestamp,
+        eth_address: String,
+        token: Option<AccountId>,
+        hash_algorithm: HashAlgorithm,
+        timelocks: Timelocks,
+        safety_deposit: U128,
+    },
+
+    #[event_version("1.0.0")]
+    CrossChainSwapCompleted {
+        id: String,
+        sender: AccountId,
+        receiver: AccountId,
+        amount: U128,
+        fee: U128,
+        status: EscrowStatus,
+        hashlock: String,
+        preimage: String,
+        eth_address: String,
+        eth_tx_hash: String,
+    },
+
+    #[event_version("1.0.0")]
+    CrossChainHtlcRefunded {
+        id: String,
+        sender: AccountId,
+        receiver: AccountId,
+        amount: U128,
+        status: EscrowStatus,
+        hashlock: String,
+        timelock: Timestamp,
+        eth_address: String,
+    },
+
+    #[event_version("1.0.0")]
+    CrossChainPublicWithdrawn {
+        id: String,
+        caller: AccountId,
+        sender: AccountId,
+        receiver: AccountId,
+        amount: U128,
+        fee: U128,
+        status: EscrowStatus,
+        hashlock: String,
+        preimage: String,
+        bounty: U128,
+    },
+
+    #[event_version("1.0.0")]
+    CrossChainPublicCancelled {
+        id: String,
+        caller: AccountId,
+        sender: AccountId,
+        receiver: AccountId,
+        amount: U128,
+        status: EscrowStatus,
+        hashlock: String,
+        bounty: U128,
+    },
+
+    #[event_version("1.0.0")]
+    SafetyDepositLocked {
+        id: String,
+        resolver: AccountId,
+        amount: U128,
+    },
+
+    #[event_version("1.0.0")]
+    SafetyDepositReturned {
+        id: String,
+        resolver: AccountId,
+        amount: U128,
+    },
+
+    #[event_version("1.0.0")]
+    SafetyDepositSlashed {
+        id: String,
+        resolver: AccountId,
+        caller: AccountId,
+        amount: U128,
+    },
+
+    #[event_version("1.0.0")]
+    PartialFillSwapCreated {
+        swap_id: String,
+        sender: AccountId,
+        total_amount: U128,
+        timelock: Timestamp,
+        eth_address: String,
+        auction: Option<DutchAuction>,
+        secrets: Option<MerkleSecrets>,
+    },
+
+    #[event_version("1.0.0")]
+    PartialFillCreated {
+        fill_id: String,
+        swap_id: String,
+        sender: AccountId,
+        receiver: AccountId,
+        fill_amount: U128,
+        remaining_amount: U128,
+        hashlock: String,
+        timelock: Timestamp,
+        eth_address: String,
+        hash_algorithm: HashAlgorithm,
+        min_destination_amount: Option<U128>,
+        secret_index: Option<u32>,
+    },
+
+    #[event_version("1.0.0")]
+    PartialFillCompleted {
+        fill_id: String,
+        swap_id: String,
+        sender: AccountId,
+        receiver: AccountId,
+        fill_amount: U128,
+        fee: U128,
+        status: EscrowStatus,
+        hashlock: String,
+        preimage: String,
+        eth_address: String,
+        eth_tx_hash: String,
+    },
+
+    #[event_version("1.0.0")]
+    PartialFillRefunded {
+        fill_id: String,
+        swap_id: String,
+        sender: AccountId,
+        receiver: AccountId,
+        fill_amount: U128,
+        remaining_amount: U128,
+        status: EscrowStatus,
+        hashlock: String,
+        timelock: Timestamp,
+    },
+
+    #[event_version("1.0.0")]
+    PartialFillSwapRefunded {
+        swap_id: String,
+        sender: AccountId,
+        amount: U128,
+    },
+
+    #[event_version("1.0.0")]
+    PartialFillSwapCancelled {
+        swap_id: String,
+        sender: AccountId,
+        refunded_amount: U128,
+        filled_amount: U128,
+    },
+
+    #[event_version("1.0.0")]
+    SwapStatusChanged {
+        swap_id: String,
+        from: SwapStatus,
+        to: SwapStatus,
+    },
+
+    #[event_version("1.0.0")]
+    ResolverAuthorized { resolver: AccountId },
+
+    #[event_version("1.0.0")]
+    ResolverRevoked { resolver: AccountId },
+
+    #[event_version("1.0.0")]
+    PermissionlessModeSet { permissionless: bool },
+
+    #[event_version("1.0.0")]
+    RoleGranted {
+        role: Role,
+        account_id: AccountId,
+        by: AccountId,
+    },
+
+    #[event_version("1.0.0")]
+    RoleRevoked {
+        role: Role,
+        account_id: AccountId,
+        by: AccountId,
+    },
+
+    #[event_version("1.0.0")]
+    OwnershipProposed {
+        owner: AccountId,
+        pending_owner: AccountId,
+    },
+
+    #[event_version("1.0.0")]
+    OwnershipTransferred {
+        previous_owner: AccountId,
+        new_owner: AccountId,
+    },
+
+    #[event_version("1.0.0")]
+    FeeUpdated { fee_bps: u16, by: AccountId },
+
+    #[event_version("1.0.0")]
+    FeesWithdrawn {
+        token: Option<AccountId>,
+        amount: U128,
+        receiver: AccountId,
+    },
+
+    #[event_version("1.0.0")]
+    ContractPaused { by: AccountId },
+
+    #[event_version("1.0.0")]
+    ContractUnpaused { by: AccountId },
+
+    #[event_version("1.0.0")]
+    EscrowArchived {
+        id: String,
+        sender: AccountId,
+        status: EscrowStatus,
+        hashlock: String,
+        freed_bytes: StorageUsage,
+    },
+
+    #[event_version("1.0.0")]
+    PartialFillSwapArchived {
+        swap_id: String,
+        sender: AccountId,
+        status: SwapStatus,
+        freed_bytes: StorageUsage,
+    },
+
+    #[event_version("1.0.0")]
+    PayoutCompleted {
+        escrow_id: Option<String>,
+        account_id: AccountId,
+        amount: U128,
+        token: Option<AccountId>,
+    },
+
+    #[event_version("1.0.0")]
+    PayoutFailed {
+        escrow_id: Option<String>,
+        account_id: AccountId,
+        amount: U128,
+        token: Option<AccountId>,
+    },
+
+    #[event_version("1.0.0")]
+    NftHtlcCreated {
+        id: String,
+        sender: AccountId,
+        receiver: AccountId,
+        nft_contract: AccountId,
+        token_id: String,
+        hashlock: String,
+        timelock: Timestamp,
+        eth_address: String,
+        hash_algorithm: HashAlgorithm,
+    },
+
+    #[event_version("1.0.0")]
+    NftSwapCompleted {
+        id: String,
+        sender: AccountId,
+        receiver: AccountId,
+        nft_contract: AccountId,
+        token_id: String,
+        status: EscrowStatus,
+        hashlock: String,
+        preimage: String,
+        eth_address: String,
+        eth_tx_hash: String,
+    },
+
+    #[event_version("1.0.0")]
+    NftHtlcRefunded {
+        id: String,
+        sender: AccountId,
+        receiver: AccountId,
+        nft_contract: AccountId,
+        token_id: String,
+        status: EscrowStatus,
+        hashlock: String,
+        timelock: Timestamp,
+        eth_address: String,
+    },
+
+    #[event_version("1.0.0")]
+    NftPayoutCompleted {
+        escrow_id: String,
+        account_id: AccountId,
+        nft_contract: AccountId,
+        token_id: String,
+    },
+
+    #[event_version("1.0.0")]
+    NftPayoutFailed {
+        escrow_id: String,
+        account_id: AccountId,
+        nft_contract: AccountId,
+        token_id: String,
+    },
+
+    #[event_version("1.0.0")]
+    StateMigrated { from_version: u32, to_version: u32 },
+}
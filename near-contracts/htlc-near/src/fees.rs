@@ -0,0 +1,101 @@
+use near_sdk::json_types::U128;
+use near_sdk::{env, near_bindgen, AccountId};
+
+use crate::views::page_bounds;
+use crate::{HTLCNear, HTLCNearExt, HtlcEvent, Role};
+
+/// Fee of a new deployment, in basis points: the 0.3% bridge fee.
+pub const DEFAULT_FEE_BPS: u16 = 30;
+
+/// Highest fee the fee manager can set: 10%.
+pub const MAX_FEE_BPS: u16 = 1_000;
+
+const BPS_DENOMINATOR: u128 = 10_000;
+
+impl HTLCNear {
+    /// Split `amount` settled to a receiver into the payout and the protocol
+    /// fee at `fee_bps`, the rate the escrow was created with, and credit
+    /// the fee to the treasury balance of `token`.
+    pub(crate) fn take_fee(
+        &mut self,
+        amount: U128,
+        fee_bps: u16,
+        token: &Option<AccountId>,
+    ) -> (U128, U128) {
+        let fee = amount.0 * u128::from(fee_bps) / BPS_DENOMINATOR;
+        if fee > 0 {
+            let balance = self.fee_balances.get(token).unwrap_or(U128(0));
+            self.fee_balances.insert(token, &U128(balance.0 + fee));
+        }
+        (U128(amount.0 - fee), U128(fee))
+    }
+}
+
+#[near_bindgen]
+impl HTLCNear {
+    /// Set the fee of escrows created from now on, taken from their
+    /// settlement to a receiver, in basis points (fee manager role)
+    pub fn set_fee_bps(&mut self, fee_bps: u16) {
+        self.assert_role(Role::FeeManager);
+        assert!(fee_bps <= MAX_FEE_BPS, "Fee too high");
+        self.fee_bps = fee_bps;
+
+        HtlcEvent::FeeUpdated {
+            fee_bps,
+            by: env::predecessor_account_id(),
+        }
+        .emit();
+    }
+
+    /// Pay `amount` of collected fees in `token` (`None` for NEAR), all of
+    /// them if omitted, to `receiver` or the caller (fee manager role)
+    pub fn withdraw_fees(
+        &mut self,
+        token: Option<AccountId>,
+        amount: Option<U128>,
+        receiver: Option<AccountId>,
+    ) -> U128 {
+        self.assert_role(Role::FeeManager);
+        let balance = self.fee_balances.get(&token).unwrap_or(U128(0));
+        let amount = amount.unwrap_or(balance);
+        assert!(amount.0 > 0, "Nothing to withdraw");
+        assert!(amount.0 <= balance.0, "Insufficient fee balance");
+
+        if amount == balance {
+            self.fee_balances.remove(&token);
+        } else {
+            self.fee_balances
+                .insert(&token, &U128(balance.0 - amount.0));
+        }
+
+        let receiver = receiver.unwrap_or_else(env::predecessor_account_id);
+        self.pay_out(None, receiver.clone(), amount, token.clone());
+
+        HtlcEvent::FeesWithdrawn {
+            token,
+            amount,
+            receiver,
+        }
+        .emit();
+        amount
+    }
+
+    pub fn get_fee_bps(&self) -> u16 {
+        self.fee_bps
+    }
+
+    /// Collected fees in `token`, `None` for NEAR
+    pub fn get_fee_balance(&self, token: Option<AccountId>) -> U128 {
+        self.fee_balances.get(&token).unwrap_or(U128(0))
+    }
+
+    /// Collected fees per token, paginated
+    pub fn get_fee_balances(
+        &self,
+        from_index: Option<u64>,
+        limit: Option<u64>,
+    ) -> Vec<(Option<AccountId>, U128)> {
+        let (skip, take) = page_bounds(from_index, limit);
+        self.fee_balances.iter().skip(skip).take(take).collect()
+    }
+}
@@ -0,0 +1,72 @@
+use near_contract_standards::fungible_token::receiver::FungibleTokenReceiver;
+use near_sdk::json_types::{Base64VecU8, U128};
+use near_sdk::serde::{Deserialize, Serialize};
+use near_sdk::{env, near_bindgen, AccountId, PromiseOrValue, Timestamp};
+
+use crate::{HTLCNear, HTLCNearExt, HashAlgorithm, Timelocks};
+
+/// `msg` of an `ft_transfer_call` to this contract: the escrow to create
+/// with the transferred tokens, tagged with `"kind"`, e.g.
+/// `{"kind":"htlc","receiver":"bob.near","hashlock":"<base64>","timelock":1700000000000,"eth_address":"0x.."}`.
+#[derive(Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde", tag = "kind", rename_all = "snake_case")]
+pub enum FtEscrowMsg {
+    Htlc(HtlcArgs),
+    CrossChain(HtlcArgs),
+    /// Destination escrow of an ETH → NEAR swap, for authorized resolvers
+    Destination(HtlcArgs),
+}
+
+/// Parameters of an HTLC or cross-chain HTLC, funded by the caller.
+#[derive(Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct HtlcArgs {
+    pub receiver: AccountId,
+    pub hashlock: Base64VecU8,
+    pub timelock: Timestamp,
+    pub eth_address: String,
+    /// SHA-256 if omitted
+    pub hash_algorithm: Option<HashAlgorithm>,
+    /// Fusion+ stages of a cross-chain HTLC; `timelock` alone if omitted
+    pub timelocks: Option<Timelocks>,
+}
+
+#[near_bindgen]
+impl FungibleTokenReceiver for HTLCNear {
+    /// Escrow the transferred tokens as described by `msg`. The token
+    /// contract is the predecessor and is recorded on the escrow; receivers
+    /// should check it before acting on the escrow. `sender_id` must trust
+    /// the token contract. Panics, and so refunds the transfer, if `msg` is
+    /// invalid or the escrow can't be created.
+    fn ft_on_transfer(
+        &mut self,
+        sender_id: AccountId,
+        amount: U128,
+        msg: String,
+    ) -> PromiseOrValue<U128> {
+        let token = Some(env::predecessor_account_id());
+        let escrow_msg: FtEscrowMsg = near_sdk::serde_json::from_str(&msg)
+            .unwrap_or_else(|_| env::panic_str("Invalid escrow message"));
+        self.assert_token_contract_trusted(&sender_id);
+
+        match escrow_msg {
+            FtEscrowMsg::Htlc(args) => self.internal_create_htlc(sender_id, amount, token, args),
+            // The resolver locks its safety deposit with `lock_safety_deposit`
+            FtEscrowMsg::CrossChain(args) => self.internal_create_cross_chain_htlc(
+                sender_id,
+                amount,
+                token,
+                U128(0),
+                false,
+                args,
+            ),
+            FtEscrowMsg::Destination(args) => {
+                self.assert_resolver(&sender_id);
+                self.internal_create_cross_chain_htlc(sender_id, amount, token, U128(0), true, args)
+            }
+        };
+
+        // The whole amount is held in escrow
+        PromiseOrValue::Value(U128(0))
+    }
+}
@@ -0,0 +1,41 @@
+use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
+use near_sdk::serde::{Deserialize, Serialize};
+use near_sdk::{env, NearSchema};
+use sha2::Digest;
+
+/// Hash function an escrow's hashlock was built with. EVM and TRON
+/// counterparts may lock with keccak256, so the same secret has to be
+/// checked with the same function on this side.
+#[derive(
+    BorshDeserialize,
+    BorshSerialize,
+    Serialize,
+    Deserialize,
+    NearSchema,
+    Clone,
+    Copy,
+    PartialEq,
+    Eq,
+    Debug,
+    Default,
+)]
+#[serde(crate = "near_sdk::serde", rename_all = "snake_case")]
+#[abi(json)]
+pub enum HashAlgorithm {
+    #[default]
+    Sha256,
+    Keccak256,
+}
+
+impl HashAlgorithm {
+    pub fn hash(&self, preimage: &[u8]) -> Vec<u8> {
+        match self {
+            HashAlgorithm::Sha256 => sha2::Sha256::digest(preimage).to_vec(),
+            HashAlgorithm::Keccak256 => env::keccak256(preimage),
+        }
+    }
+
+    pub fn matches(&self, preimage: &[u8], hashlock: &[u8]) -> bool {
+        self.hash(preimage) == hashlock
+    }
+}
@@ -1,11 +1,46 @@
 use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
-use near_sdk::collections::UnorderedMap;
+use near_sdk::collections::{LookupMap, LookupSet, UnorderedMap, UnorderedSet};
 use near_sdk::json_types::{Base64VecU8, U128};
 use near_sdk::serde::{Deserialize, Serialize};
 use near_sdk::{
-    env, near_bindgen, AccountId, NearToken, PanicOnDefault, Promise, Timestamp,
+    env, near_bindgen, AccountId, BorshStorageKey, NearSchema, PanicOnDefault, Timestamp,
 };
-use sha2::Digest;
+
+mod auction;
+mod cleanup;
+mod events;
+mod fees;
+mod fungible_token;
+mod hashlock;
+mod merkle;
+mod migration;
+mod nft;
+mod payout;
+mod roles;
+mod safety_deposit;
+mod status;
+mod storage;
+mod timelocks;
+mod views;
+
+pub use crate::auction::{AuctionPoint, DutchAuction};
+pub use crate::cleanup::{ArchivedEscrow, ArchivedEscrowView};
+pub use crate::events::HtlcEvent;
+pub use crate::fungible_token::{FtEscrowMsg, HtlcArgs};
+pub use crate::hashlock::HashAlgorithm;
+pub use crate::merkle::{FillSecret, MerkleSecrets};
+pub use crate::migration::StateVersion;
+pub use crate::nft::NftHTLC;
+use crate::migration::{MigrationCursor, RecordMap};
+pub use crate::roles::Role;
+pub use crate::status::{EscrowStatus, SwapStatus};
+pub use crate::storage::StorageAccount;
+pub use crate::timelocks::{TimelockStage, Timelocks};
+pub use crate::views::{
+    CrossChainHTLCView, EscrowView, HTLCContractView, NftHTLCView,
+    PartialFillSwapView, PartialFillView, SwapProgressView,
+};
+use crate::views::page_bounds;
 
 #[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone)]
 #[serde(crate = "near_sdk::serde")]
@@ -15,9 +50,13 @@ pub struct HTLCContract {
     pub amount: U128,
     pub hashlock: Vec<u8>,
     pub timelock: Timestamp,
-    pub withdrawn: bool,
-    pub refunded: bool,
+    pub status: EscrowStatus,
     pub eth_address: String,
+    // NEP-141 token contract, `None` for native NEAR
+    pub token: Option<AccountId>,
+    pub hash_algorithm: HashAlgorithm,
+    // Protocol fee in basis points at creation, charged on settlement
+    pub fee_bps: u16,
 }
 
 // Cross-chain swap extension for 1inch Fusion+
@@ -29,10 +68,22 @@ pub struct CrossChainHTLC {
     pub amount: U128,
     pub hashlock: Vec<u8>,
     pub timelock: Timestamp,
-    pub withdrawn: bool,
-    pub refunded: bool,
+    pub status: EscrowStatus,
     pub eth_address: String,
     pub eth_tx_hash: Option<String>, // For verification
+    // NEP-141 token contract, `None` for native NEAR
+    pub token: Option<AccountId>,
+    pub hash_algorithm: HashAlgorithm,
+    // Fusion+ stages; `timelock` is the end of the withdrawal stages
+    pub timelocks: Timelocks,
+    // NEAR held apart from `amount`, slashed to whoever settles the escrow
+    // in a public stage and otherwise returned to the sender
+    pub safety_deposit: U128,
+    // Token escrow of a resolver that can't settle until it locks its
+    // safety deposit with `lock_safety_deposit`
+    pub safety_deposit_pending: bool,
+    // Protocol fee in basis points at creation, charged on settlement
+    pub fee_bps: u16,
 }
 
 // Partial Fill HTLC for 1inch Fusion+ Dutch Auctions
@@ -46,11 +97,18 @@ pub struct PartialFill {
     pub fill_amount: U128,
     pub hashlock: Vec<u8>,
     pub timelock: Timestamp,
-    pub completed: bool,
-    pub refunded: bool,
+    pub status: EscrowStatus,
     pub eth_address: String,
     pub eth_tx_hash: Option<String>,
     pub created_at: Timestamp,
+    pub hash_algorithm: HashAlgorithm,
+    // Destination amount the resolver owes at the auction rate of creation,
+    // `None` if the swap has no auction
+    pub min_destination_amount: Option<U128>,
+    // Index of the swap's Merkle secret this fill is locked with
+    pub secret_index: Option<u32>,
+    // Protocol fee in basis points of the parent swap, charged on completion
+    pub fee_bps: u16,
 }
 
 // Main Swap tracking multiple partial fills
@@ -59,15 +117,73 @@ pub struct PartialFill {
 pub struct PartialFillSwap {
     pub swap_id: String,
     pub sender: AccountId,
-    pub receiver: AccountId,
     pub total_amount: U128,
+    // Allocated to fills that are open or completed
     pub filled_amount: U128,
+    // Locked in the swap and not allocated to any fill yet
     pub remaining_amount: U128,
     pub eth_address: String,
     pub timelock: Timestamp,
-    pub completed: bool,
+    pub status: SwapStatus,
     pub created_at: Timestamp,
     pub fill_count: u32,
+    pub auction: Option<DutchAuction>,
+    pub secrets: Option<MerkleSecrets>,
+    // Highest secret index revealed by a fill; each index unlocks one fill
+    pub last_secret_index: Option<u32>,
+    // Fills neither completed nor refunded yet
+    pub open_fill_count: u32,
+    // Protocol fee in basis points at creation, charged on each fill
+    pub fee_bps: u16,
+}
+
+/// Kind of escrow an ID is issued for. Every kind draws from the same
+/// contract-wide nonce, so an ID is never handed out twice.
+#[derive(Serialize, Deserialize, NearSchema, Clone, Copy)]
+#[serde(crate = "near_sdk::serde", rename_all = "snake_case")]
+#[abi(json)]
+pub enum EscrowKind {
+    Htlc,
+    CrossChain,
+    PartialFillSwap,
+    PartialFill,
+    Nft,
+}
+
+impl EscrowKind {
+    fn prefix(&self) -> &'static str {
+        match self {
+            EscrowKind::Htlc => "htlc",
+            EscrowKind::CrossChain => "cc",
+            EscrowKind::PartialFillSwap => "pf-swap",
+            EscrowKind::PartialFill => "fill",
+            EscrowKind::Nft => "nft",
+        }
+    }
+
+    pub fn escrow_id(&self, nonce: u64) -> String {
+        format!("{}-{}", self.prefix(), nonce)
+    }
+}
+
+/// Prefixes for collections added after the original layout, which keeps
+/// its byte-string prefixes.
+#[derive(BorshSerialize, BorshStorageKey)]
+enum StorageKey {
+    EscrowsBySender,
+    SenderEscrows { account_hash: Vec<u8> },
+    EscrowsByReceiver,
+    ReceiverEscrows { account_hash: Vec<u8> },
+    FillsBySwap,
+    SwapFills { swap_hash: Vec<u8> },
+    StorageAccounts,
+    ArchivedEscrows,
+    ClaimableBalances,
+    NftEscrows,
+    RoleMembers,
+    RoleAccounts { role: Role },
+    FeeBalances,
+    TrustedTokenContracts,
 }
 
 #[near_bindgen]
@@ -80,20 +196,77 @@ pub struct HTLCNear {
     partial_fills: UnorderedMap<String, PartialFill>,
     owner: AccountId,
     authorized_resolvers: UnorderedMap<AccountId, bool>,
+    // Blocks new escrows and fills; withdraw and refund stay open
+    paused: bool,
+    // Monotonic counter behind every escrow, swap and fill ID
+    next_nonce: u64,
+    // HTLC, cross-chain and partial fill IDs per sender / receiver
+    escrows_by_sender: LookupMap<AccountId, UnorderedSet<String>>,
+    escrows_by_receiver: LookupMap<AccountId, UnorderedSet<String>>,
+    // Fill IDs of each partial fill swap
+    fills_by_swap: LookupMap<String, UnorderedSet<String>>,
+    // NEP-145 balances paying for the storage of each account's escrows
+    storage_accounts: LookupMap<AccountId, StorageAccount>,
+    // Settled escrows stay in their maps this long past their timelock
+    retention_period_ms: u64,
+    // Final status and hashlock of escrows removed by cleanup
+    archived_escrows: LookupMap<String, ArchivedEscrow>,
+    // Records still in an older layout after `migrate`, if any
+    migration: Option<MigrationCursor>,
+    // Payouts whose transfer failed, per account and token (`None` for NEAR),
+    // claimable with `claim_payout`
+    claimable_balances: LookupMap<(AccountId, Option<AccountId>), U128>,
+    // NEP-171 tokens locked under a hashlock
+    nft_escrows: UnorderedMap<String, NftHTLC>,
+    // Smallest safety deposit behind a cross-chain HTLC created by a resolver
+    min_safety_deposit: U128,
+    // Lets any account act as a resolver
+    permissionless: bool,
+    // Accounts granted each admin role besides the owner
+    role_members: LookupMap<Role, UnorderedSet<AccountId>>,
+    // Account offered ownership, until it accepts
+    pending_owner: Option<AccountId>,
+    // Protocol fee taken from each settlement to a receiver, in basis points
+    fee_bps: u16,
+    // Collected fees per token (`None` for NEAR), paid out with `withdraw_fees`
+    fee_balances: UnorderedMap<Option<AccountId>, U128>,
+    // (account, token contract) pairs whose transfer callbacks may create
+    // escrows in the account's name
+    trusted_token_contracts: LookupSet<(AccountId, AccountId)>,
 }
 
 #[near_bindgen]
 impl HTLCNear {
     #[init]
     pub fn new(owner: AccountId) -> Self {
-        Self {
+        let contract = Self {
             contracts: UnorderedMap::new(b"c"),
             cross_chain_contracts: UnorderedMap::new(b"cc".as_slice()),
             partial_fill_swaps: UnorderedMap::new(b"s".as_slice()),
             partial_fills: UnorderedMap::new(b"f".as_slice()),
             owner: owner.clone(),
             authorized_resolvers: UnorderedMap::new(b"r"),
-        }
+            paused: false,
+            next_nonce: 0,
+            escrows_by_sender: LookupMap::new(StorageKey::EscrowsBySender),
+            escrows_by_receiver: LookupMap::new(StorageKey::EscrowsByReceiver),
+            fills_by_swap: LookupMap::new(StorageKey::FillsBySwap),
+            storage_accounts: LookupMap::new(StorageKey::StorageAccounts),
+            retention_period_ms: cleanup::DEFAULT_RETENTION_PERIOD_MS,
+            archived_escrows: LookupMap::new(StorageKey::ArchivedEscrows),
+            migration: None,
+            claimable_balances: LookupMap::new(StorageKey::ClaimableBalances),
+            nft_escrows: UnorderedMap::new(StorageKey::NftEscrows),
+            min_safety_deposit: U128(safety_deposit::DEFAULT_MIN_SAFETY_DEPOSIT),
+            permissionless: false,
+            role_members: LookupMap::new(StorageKey::RoleMembers),
+            pending_owner: None,
+            fee_bps: fees::DEFAULT_FEE_BPS,
+            fee_balances: UnorderedMap::new(StorageKey::FeeBalances),
+            trusted_token_contracts: LookupSet::new(StorageKey::TrustedTokenContracts),
+        };
+        migration::CURRENT_STATE_VERSION.write();
+        contract
     }
 
     #[payable]
@@ -103,11 +276,46 @@ impl HTLCNear {
         hashlock: Base64VecU8,
         timelock: Timestamp,
         eth_address: String,
+        hash_algorithm: Option<HashAlgorithm>,
     ) -> String {
-        let sender = env::predecessor_account_id();
-        let amount = env::attached_deposit();
+        self.internal_create_htlc(
+            env::predecessor_account_id(),
+            U128(env::attached_deposit().as_yoctonear()),
+            None,
+            HtlcArgs {
+                receiver,
+                hashlock,
+                timelock,
+                eth_address,
+                hash_algorithm,
+                timelocks: None,
+            },
+        )
+    }
+
+    pub(crate) fn internal_create_htlc(
+        &mut self,
+        sender: AccountId,
+        amount: U128,
+        token: Option<AccountId>,
+        args: HtlcArgs,
+    ) -> String {
+        let HtlcArgs {
+            receiver,
+            hashlock,
+            timelock,
+            eth_address,
+            hash_algorithm,
+            timelocks,
+        } = args;
+        self.assert_not_paused();
+        assert!(
+            timelocks.is_none(),
+            "Timelock stages are only supported on cross-chain HTLCs"
+        );
+        let initial_storage = env::storage_usage();
 
-        assert!(amount > NearToken::from_yoctonear(0), "Amount must be greater than 0");
+        assert!(amount.0 > 0, "Amount must be greater than 0");
         assert!(
             timelock > env::block_timestamp_ms(),
             "Timelock must be in the future"
@@ -115,44 +323,50 @@ impl HTLCNear {
         assert!(!hashlock.0.is_empty(), "Hashlock cannot be empty");
         assert!(hashlock.0.len() == 32, "Hashlock must be 32 bytes");
 
-        // Generate unique contract ID
-        let contract_id = format!(
-            "{}-{}-{}-{}",
-            sender,
-            receiver,
-            amount,
-            env::block_timestamp_ms()
-        );
+        let contract_id = self.next_escrow_id(EscrowKind::Htlc);
+        assert!(self.contracts.record(&contract_id).is_none(), "Escrow ID already in use");
 
         let contract = HTLCContract {
-            sender: sender.clone(),
+            sender,
             receiver,
-            amount: U128(amount.as_yoctonear()),
+            amount,
             hashlock: hashlock.0,
             timelock,
-            withdrawn: false,
-            refunded: false,
+            status: EscrowStatus::Active,
             eth_address,
+            token,
+            hash_algorithm: hash_algorithm.unwrap_or_default(),
+            fee_bps: self.fee_bps,
         };
 
         self.contracts.insert(&contract_id, &contract);
+        self.index_escrow(&contract_id, &contract.sender, &contract.receiver);
+        self.charge_storage(&contract.sender, initial_storage);
 
-        env::log_str(&format!(
-            "HTLC created: {}, sender: {}, amount: {}, timelock: {}",
-            contract_id, sender, amount, timelock
-        ));
+        HtlcEvent::HtlcCreated {
+            id: contract_id.clone(),
+            sender: contract.sender,
+            receiver: contract.receiver,
+            amount: contract.amount,
+            hashlock: hex::encode(&contract.hashlock),
+            timelock: contract.timelock,
+            eth_address: contract.eth_address,
+            token: contract.token,
+            hash_algorithm: contract.hash_algorithm,
+        }
+        .emit();
 
         contract_id
     }
 
     pub fn withdraw(&mut self, contract_id: String, preimage: Base64VecU8) {
+        self.migrate_escrow(&contract_id);
         let mut contract = self
             .contracts
-            .get(&contract_id)
+            .record(&contract_id)
             .expect("Contract does not exist");
 
-        assert!(!contract.withdrawn, "Already withdrawn");
-        assert!(!contract.refunded, "Already refunded");
+        contract.status.transition(EscrowStatus::Withdrawn);
         assert!(
             env::predecessor_account_id() == contract.receiver,
             "Only receiver can withdraw"
@@ -163,33 +377,44 @@ impl HTLCNear {
         );
 
         // Verify preimage
-        let hash = sha2::Sha256::digest(&preimage.0);
-        assert_eq!(
-            hash.as_slice(),
-            &contract.hashlock,
+        assert!(
+            contract.hash_algorithm.matches(&preimage.0, &contract.hashlock),
             "Invalid preimage"
         );
 
-        contract.withdrawn = true;
         self.contracts.insert(&contract_id, &contract);
 
-        // Transfer NEAR to receiver
-        Promise::new(contract.receiver.clone()).transfer(NearToken::from_yoctonear(contract.amount.0));
+        // Transfer NEAR to receiver, less the protocol fee
+        let (payout, fee) = self.take_fee(contract.amount, contract.fee_bps, &contract.token);
+        self.pay_out(
+            Some(contract_id.clone()),
+            contract.receiver.clone(),
+            payout,
+            contract.token.clone(),
+        );
 
-        env::log_str(&format!(
-            "HTLC withdrawn: {}, receiver: {}, amount: {}",
-            contract_id, contract.receiver, contract.amount.0
-        ));
+        HtlcEvent::HtlcWithdrawn {
+            id: contract_id,
+            sender: contract.sender,
+            receiver: contract.receiver,
+            amount: contract.amount,
+            fee,
+            status: contract.status,
+            hashlock: hex::encode(&contract.hashlock),
+            preimage: hex::encode(&preimage.0),
+            eth_address: contract.eth_address,
+        }
+        .emit();
     }
 
     pub fn refund(&mut self, contract_id: String) {
+        self.migrate_escrow(&contract_id);
         let mut contract = self
             .contracts
-            .get(&contract_id)
+            .record(&contract_id)
             .expect("Contract does not exist");
 
-        assert!(!contract.withdrawn, "Already withdrawn");
-        assert!(!contract.refunded, "Already refunded");
+        contract.status.transition(EscrowStatus::Refunded);
         assert!(
             env::predecessor_account_id() == contract.sender,
             "Only sender can refund"
@@ -199,67 +424,212 @@ impl HTLCNear {
             "Timelock not expired"
         );
 
-        contract.refunded = true;
         self.contracts.insert(&contract_id, &contract);
 
         // Transfer NEAR back to sender
-        Promise::new(contract.sender.clone()).transfer(NearToken::from_yoctonear(contract.amount.0));
+        self.pay_out(
+            Some(contract_id.clone()),
+            contract.sender.clone(),
+            contract.amount,
+            contract.token.clone(),
+        );
 
-        env::log_str(&format!(
-            "HTLC refunded: {}, sender: {}, amount: {}",
-            contract_id, contract.sender, contract.amount.0
-        ));
+        HtlcEvent::HtlcRefunded {
+            id: contract_id,
+            sender: contract.sender,
+            receiver: contract.receiver,
+            amount: contract.amount,
+            status: contract.status,
+            hashlock: hex::encode(&contract.hashlock),
+            timelock: contract.timelock,
+            eth_address: contract.eth_address,
+        }
+        .emit();
     }
 
-    pub fn get_contract(&self, contract_id: String) -> Option<(String, String, String, String, u64, bool, bool, String)> {
-        self.contracts.get(&contract_id).map(|contract| (
-            contract.sender.to_string(),
-            contract.receiver.to_string(),
-            contract.amount.0.to_string(),
-            hex::encode(&contract.hashlock),
-            contract.timelock,
-            contract.withdrawn,
-            contract.refunded,
-            contract.eth_address
-        ))
+    pub fn get_contract(&self, contract_id: String) -> Option<HTLCContractView> {
+        self.contracts
+            .record(&contract_id)
+            .map(|contract| HTLCContractView::new(contract_id, contract))
     }
 
+    /// Whether `preimage` unlocks the HTLC, cross-chain HTLC, NFT escrow or
+    /// partial fill `contract_id`, using the escrow's hash algorithm
     pub fn check_preimage(&self, contract_id: String, preimage: Base64VecU8) -> bool {
-        if let Some(contract) = self.contracts.get(&contract_id) {
-            let hash = sha2::Sha256::digest(&preimage.0);
-            return hash.as_slice() == &contract.hashlock;
-        }
-        false
+        let lock = if let Some(contract) = self.contracts.record(&contract_id) {
+            Some((contract.hash_algorithm, contract.hashlock))
+        } else if let Some(contract) = self.cross_chain_contracts.record(&contract_id) {
+            Some((contract.hash_algorithm, contract.hashlock))
+        } else if let Some(escrow) = self.nft_escrows.get(&contract_id) {
+            Some((escrow.hash_algorithm, escrow.hashlock))
+        } else {
+            self.partial_fills
+                .record(&contract_id)
+                .map(|fill| (fill.hash_algorithm, fill.hashlock))
+        };
+        lock.is_some_and(|(algorithm, hashlock)| algorithm.matches(&preimage.0, &hashlock))
     }
 
     pub fn get_contract_count(&self) -> u64 {
         self.contracts.len()
     }
 
-    pub fn get_all_contracts(&self) -> Vec<(String, (String, String, String, String, u64, bool, bool, String))> {
-        self.contracts.iter().map(|(id, contract)| (
-            id,
-            (
-                contract.sender.to_string(),
-                contract.receiver.to_string(),
-                contract.amount.0.to_string(),
-                hex::encode(&contract.hashlock),
-                contract.timelock,
-                contract.withdrawn,
-                contract.refunded,
-                contract.eth_address
-            )
-        )).collect()
+    /// List HTLCs page by page. `limit` bounds the records scanned, so a page
+    /// filtered by `status` may hold fewer than `limit` entries; continue from
+    /// `from_index + limit` until it reaches `get_contract_count`.
+    pub fn get_all_contracts(
+        &self,
+        from_index: Option<u64>,
+        limit: Option<u64>,
+        status: Option<EscrowStatus>,
+    ) -> Vec<HTLCContractView> {
+        let (skip, take) = page_bounds(from_index, limit);
+        self.contracts
+            .records(skip, take)
+            .map(|(id, contract)| HTLCContractView::new(id, contract))
+            .filter(|view| status.is_none_or(|status| view.status == status))
+            .collect()
     }
 
-    // Emergency functions (owner only)
+    // Emergency functions (pauser role)
+    /// Pause escrow and fill creation. Withdraw and refund keep working so
+    /// funds already locked can always leave the contract.
     pub fn emergency_pause(&mut self) {
-        assert_eq!(
-            env::predecessor_account_id(),
-            self.owner,
-            "Only owner can pause"
+        self.assert_role(Role::Pauser);
+        assert!(!self.paused, "Contract already paused");
+        self.paused = true;
+        HtlcEvent::ContractPaused {
+            by: env::predecessor_account_id(),
+        }
+        .emit();
+    }
+
+    pub fn unpause(&mut self) {
+        self.assert_role(Role::Pauser);
+        assert!(self.paused, "Contract not paused");
+        self.assert_migrated();
+        self.paused = false;
+        HtlcEvent::ContractUnpaused {
+            by: env::predecessor_account_id(),
+        }
+        .emit();
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused
+    }
+
+    fn assert_not_paused(&self) {
+        assert!(!self.paused, "Contract is paused");
+    }
+
+    fn assert_resolver(&self, account_id: &AccountId) {
+        assert!(
+            self.permissionless || self.is_authorized_resolver(account_id.clone()),
+            "Only authorized resolvers"
         );
-        env::log_str("Contract paused");
+    }
+
+    /// ID the next created escrow of `kind` will receive, assuming no other
+    /// escrow is created first.
+    pub fn get_next_escrow_id(&self, kind: EscrowKind) -> String {
+        kind.escrow_id(self.next_nonce)
+    }
+
+    fn next_escrow_id(&mut self, kind: EscrowKind) -> String {
+        let id = kind.escrow_id(self.next_nonce);
+        self.next_nonce += 1;
+        id
+    }
+
+    fn index_escrow(&mut self, id: &String, sender: &AccountId, receiver: &AccountId) {
+        let mut sent = self.escrows_by_sender.get(sender).unwrap_or_else(|| {
+            UnorderedSet::new(StorageKey::SenderEscrows {
+                account_hash: env::sha256(sender.as_bytes()),
+            })
+        });
+        sent.insert(id);
+        self.escrows_by_sender.insert(sender, &sent);
+
+        let mut received = self.escrows_by_receiver.get(receiver).unwrap_or_else(|| {
+            UnorderedSet::new(StorageKey::ReceiverEscrows {
+                account_hash: env::sha256(receiver.as_bytes()),
+            })
+        });
+        received.insert(id);
+        self.escrows_by_receiver.insert(receiver, &received);
+    }
+
+    fn unindex_escrow(&mut self, id: &String, sender: &AccountId, receiver: &AccountId) {
+        if let Some(mut sent) = self.escrows_by_sender.get(sender) {
+            sent.remove(id);
+            if sent.is_empty() {
+                self.escrows_by_sender.remove(sender);
+            } else {
+                self.escrows_by_sender.insert(sender, &sent);
+            }
+        }
+        if let Some(mut received) = self.escrows_by_receiver.get(receiver) {
+            received.remove(id);
+            if received.is_empty() {
+                self.escrows_by_receiver.remove(receiver);
+            } else {
+                self.escrows_by_receiver.insert(receiver, &received);
+            }
+        }
+    }
+
+    /// Resolve an ID from any of the indexed collections
+    fn get_escrow(&self, id: String) -> Option<EscrowView> {
+        if let Some(contract) = self.contracts.record(&id) {
+            return Some(EscrowView::Htlc(HTLCContractView::new(id, contract)));
+        }
+        if let Some(contract) = self.cross_chain_contracts.record(&id) {
+            return Some(EscrowView::CrossChain(CrossChainHTLCView::new(id, contract)));
+        }
+        if let Some(escrow) = self.nft_escrows.get(&id) {
+            return Some(EscrowView::Nft(NftHTLCView::new(id, escrow)));
+        }
+        self.partial_fills
+            .record(&id)
+            .map(|fill| EscrowView::PartialFill(PartialFillView::from(fill)))
+    }
+
+    fn escrows_page(
+        &self,
+        ids: Option<UnorderedSet<String>>,
+        from_index: Option<u64>,
+        limit: Option<u64>,
+    ) -> Vec<EscrowView> {
+        let Some(ids) = ids else {
+            return vec![];
+        };
+        let (skip, take) = page_bounds(from_index, limit);
+        ids.iter()
+            .skip(skip)
+            .take(take)
+            .filter_map(|id| self.get_escrow(id))
+            .collect()
+    }
+
+    /// HTLCs, cross-chain HTLCs, NFT escrows and partial fills sent by `account_id`
+    pub fn get_escrows_by_sender(
+        &self,
+        account_id: AccountId,
+        from_index: Option<u64>,
+        limit: Option<u64>,
+    ) -> Vec<EscrowView> {
+        self.escrows_page(self.escrows_by_sender.get(&account_id), from_index, limit)
+    }
+
+    /// HTLCs, cross-chain HTLCs, NFT escrows and partial fills payable to `account_id`
+    pub fn get_escrows_by_receiver(
+        &self,
+        account_id: AccountId,
+        from_index: Option<u64>,
+        limit: Option<u64>,
+    ) -> Vec<EscrowView> {
+        self.escrows_page(self.escrows_by_receiver.get(&account_id), from_index, limit)
     }
 
     pub fn get_owner(&self) -> AccountId {
@@ -268,7 +638,8 @@ impl HTLCNear {
 
     // ======= CROSS-CHAIN METHODS FOR 1INCH FUSION+ =======
 
-    /// Create a cross-chain HTLC for NEAR → ETH swap
+    /// Create a cross-chain HTLC for NEAR → ETH swap. `safety_deposit` of
+    /// the attached NEAR is held apart from the escrowed amount.
     #[payable]
     pub fn create_cross_chain_htlc(
         &mut self,
@@ -276,11 +647,84 @@ impl HTLCNear {
         hashlock: Base64VecU8,
         timelock: Timestamp,
         eth_address: String,
+        hash_algorithm: Option<HashAlgorithm>,
+        timelocks: Option<Timelocks>,
+        safety_deposit: Option<U128>,
     ) -> String {
-        let sender = env::predecessor_account_id();
-        let amount = env::attached_deposit();
+        let (amount, safety_deposit) = safety_deposit::split_attached_deposit(safety_deposit);
+        self.internal_create_cross_chain_htlc(
+            env::predecessor_account_id(),
+            amount,
+            None,
+            safety_deposit,
+            false,
+            HtlcArgs {
+                receiver,
+                hashlock,
+                timelock,
+                eth_address,
+                hash_algorithm,
+                timelocks,
+            },
+        )
+    }
+
+    /// Create the destination escrow of an ETH → NEAR swap, paying the maker
+    /// `receiver` on this side. Authorized resolvers only, unless the
+    /// contract is permissionless, and always with the minimum safety
+    /// deposit; otherwise like `create_cross_chain_htlc`.
+    #[payable]
+    pub fn create_destination_htlc(
+        &mut self,
+        receiver: AccountId,
+        hashlock: Base64VecU8,
+        timelock: Timestamp,
+        eth_address: String,
+        hash_algorithm: Option<HashAlgorithm>,
+        timelocks: Option<Timelocks>,
+        safety_deposit: Option<U128>,
+    ) -> String {
+        let resolver = env::predecessor_account_id();
+        self.assert_resolver(&resolver);
+        let (amount, safety_deposit) = safety_deposit::split_attached_deposit(safety_deposit);
+        self.internal_create_cross_chain_htlc(
+            resolver,
+            amount,
+            None,
+            safety_deposit,
+            true,
+            HtlcArgs {
+                receiver,
+                hashlock,
+                timelock,
+                eth_address,
+                hash_algorithm,
+                timelocks,
+            },
+        )
+    }
+
+    pub(crate) fn internal_create_cross_chain_htlc(
+        &mut self,
+        sender: AccountId,
+        amount: U128,
+        token: Option<AccountId>,
+        safety_deposit: U128,
+        destination: bool,
+        args: HtlcArgs,
+    ) -> String {
+        let HtlcArgs {
+            receiver,
+            hashlock,
+            timelock,
+            eth_address,
+            hash_algorithm,
+            timelocks,
+        } = args;
+        self.assert_not_paused();
+        let initial_storage = env::storage_usage();
 
-        assert!(amount > NearToken::from_yoctonear(0), "Amount must be greater than 0");
+        assert!(amount.0 > 0, "Amount must be greater than 0");
         assert!(
             timelock > env::block_timestamp_ms(),
             "Timelock must be in the future"
@@ -288,124 +732,294 @@ impl HTLCNear {
         assert!(!hashlock.0.is_empty(), "Hashlock cannot be empty");
         assert!(hashlock.0.len() == 32, "Hashlock must be 32 bytes");
         assert!(!eth_address.is_empty(), "ETH address required");
+        let deposit_required = destination || self.requires_safety_deposit(&sender);
+        if token.is_none() && deposit_required {
+            self.assert_safety_deposit(safety_deposit);
+        }
+        // Token transfers carry no NEAR; the sender locks the deposit next
+        let safety_deposit_pending = token.is_some() && deposit_required;
+        let timelocks = timelocks.unwrap_or(Timelocks::single(timelock));
+        timelocks.assert_valid();
+        assert_eq!(
+            timelocks.public_withdrawal, timelock,
+            "Timelock must be the end of the public withdrawal stage"
+        );
 
-        let contract_id = format!(
-            "cc-{}-{}-{}-{}",
-            sender,
-            receiver,
-            amount,
-            env::block_timestamp_ms()
+        let contract_id = self.next_escrow_id(EscrowKind::CrossChain);
+        assert!(
+            self.cross_chain_contracts.record(&contract_id).is_none(),
+            "Escrow ID already in use"
         );
 
         let contract = CrossChainHTLC {
-            sender: sender.clone(),
+            sender,
             receiver,
-            amount: U128(amount.as_yoctonear()),
+            amount,
             hashlock: hashlock.0,
             timelock,
-            withdrawn: false,
-            refunded: false,
+            status: EscrowStatus::Active,
             eth_address,
             eth_tx_hash: None,
+            token,
+            hash_algorithm: hash_algorithm.unwrap_or_default(),
+            timelocks,
+            safety_deposit,
+            safety_deposit_pending,
+            fee_bps: self.fee_bps,
         };
 
         self.cross_chain_contracts.insert(&contract_id, &contract);
+        self.index_escrow(&contract_id, &contract.sender, &contract.receiver);
+        self.charge_storage(&contract.sender, initial_storage);
 
-        env::log_str(&format!(
-            "Cross-chain HTLC created: {}, sender: {}, amount: {}, timelock: {}",
-            contract_id, sender, amount, timelock
-        ));
+        HtlcEvent::CrossChainHtlcCreated {
+            id: contract_id.clone(),
+            sender: contract.sender,
+            receiver: contract.receiver,
+            amount: contract.amount,
+            hashlock: hex::encode(&contract.hashlock),
+            timelock: contract.timelock,
+            eth_address: contract.eth_address,
+            token: contract.token,
+            hash_algorithm: contract.hash_algorithm,
+            timelocks: contract.timelocks,
+            safety_deposit: contract.safety_deposit,
+        }
+        .emit();
 
         contract_id
     }
 
     /// Complete cross-chain swap with preimage
     pub fn complete_cross_chain_swap(&mut self, contract_id: String, preimage: Base64VecU8, eth_tx_hash: String) {
+        self.migrate_escrow(&contract_id);
         let mut contract = self
             .cross_chain_contracts
-            .get(&contract_id)
+            .record(&contract_id)
             .expect("Contract does not exist");
 
-        assert!(!contract.withdrawn, "Already withdrawn");
-        assert!(!contract.refunded, "Already refunded");
+        contract.status.transition(EscrowStatus::Withdrawn);
         assert!(
             env::predecessor_account_id() == contract.receiver,
             "Only receiver can withdraw"
         );
-        assert!(
-            env::block_timestamp_ms() <= contract.timelock,
-            "Timelock expired"
-        );
+        assert!(contract.timelocks.stage().is_withdrawal(), "Timelock expired");
+        safety_deposit::assert_safety_deposit_locked(&contract);
 
         // Verify preimage
-        let hash = sha2::Sha256::digest(&preimage.0);
-        assert_eq!(
-            hash.as_slice(),
-            &contract.hashlock,
+        assert!(
+            contract.hash_algorithm.matches(&preimage.0, &contract.hashlock),
             "Invalid preimage"
         );
 
-        contract.withdrawn = true;
+        storage::assert_eth_tx_hash(&eth_tx_hash);
         contract.eth_tx_hash = Some(eth_tx_hash.clone());
         self.cross_chain_contracts.insert(&contract_id, &contract);
 
-        Promise::new(contract.receiver.clone()).transfer(NearToken::from_yoctonear(contract.amount.0));
+        let (payout, fee) = self.take_fee(contract.amount, contract.fee_bps, &contract.token);
+        self.pay_out(
+            Some(contract_id.clone()),
+            contract.receiver.clone(),
+            payout,
+            contract.token.clone(),
+        );
+        self.return_safety_deposit(&contract_id, &contract);
 
-        env::log_str(&format!(
-            "Cross-chain HTLC completed: {}, receiver: {}, eth_tx: {}",
-            contract_id, contract.receiver, eth_tx_hash
-        ));
+        HtlcEvent::CrossChainSwapCompleted {
+            id: contract_id,
+            sender: contract.sender,
+            receiver: contract.receiver,
+            amount: contract.amount,
+            fee,
+            status: contract.status,
+            hashlock: hex::encode(&contract.hashlock),
+            preimage: hex::encode(&preimage.0),
+            eth_address: contract.eth_address,
+            eth_tx_hash,
+        }
+        .emit();
     }
 
     /// Refund cross-chain HTLC after timelock
     pub fn refund_cross_chain(&mut self, contract_id: String) {
+        self.migrate_escrow(&contract_id);
         let mut contract = self
             .cross_chain_contracts
-            .get(&contract_id)
+            .record(&contract_id)
             .expect("Contract does not exist");
 
-        assert!(!contract.withdrawn, "Already withdrawn");
-        assert!(!contract.refunded, "Already refunded");
+        contract.status.transition(EscrowStatus::Refunded);
         assert!(
             env::predecessor_account_id() == contract.sender,
             "Only sender can refund"
         );
         assert!(
-            env::block_timestamp_ms() > contract.timelock,
+            !contract.timelocks.stage().is_withdrawal(),
             "Timelock not expired"
         );
+        safety_deposit::assert_safety_deposit_locked(&contract);
+
+        self.cross_chain_contracts.insert(&contract_id, &contract);
+
+        self.pay_out(
+            Some(contract_id.clone()),
+            contract.sender.clone(),
+            contract.amount,
+            contract.token.clone(),
+        );
+        self.return_safety_deposit(&contract_id, &contract);
+
+        HtlcEvent::CrossChainHtlcRefunded {
+            id: contract_id,
+            sender: contract.sender,
+            receiver: contract.receiver,
+            amount: contract.amount,
+            status: contract.status,
+            hashlock: hex::encode(&contract.hashlock),
+            timelock: contract.timelock,
+            eth_address: contract.eth_address,
+        }
+        .emit();
+    }
+
+    /// Withdraw a cross-chain HTLC to its receiver once the exclusive window
+    /// is over. Callable by anyone holding the preimage, who earns the
+    /// escrow's safety deposit.
+    pub fn public_withdraw(&mut self, contract_id: String, preimage: Base64VecU8) {
+        self.migrate_escrow(&contract_id);
+        let mut contract = self
+            .cross_chain_contracts
+            .record(&contract_id)
+            .expect("Contract does not exist");
+
+        contract.status.transition(EscrowStatus::Withdrawn);
+        assert_eq!(
+            contract.timelocks.stage(),
+            TimelockStage::PublicWithdrawal,
+            "Not in the public withdrawal stage"
+        );
+        safety_deposit::assert_safety_deposit_locked(&contract);
+        assert!(
+            contract.hash_algorithm.matches(&preimage.0, &contract.hashlock),
+            "Invalid preimage"
+        );
 
-        contract.refunded = true;
         self.cross_chain_contracts.insert(&contract_id, &contract);
 
-        Promise::new(contract.sender.clone()).transfer(NearToken::from_yoctonear(contract.amount.0));
+        let caller = env::predecessor_account_id();
+        let (payout, fee) = self.take_fee(contract.amount, contract.fee_bps, &contract.token);
+        self.pay_out(
+            Some(contract_id.clone()),
+            contract.receiver.clone(),
+            payout,
+            contract.token.clone(),
+        );
+        self.slash_safety_deposit(&contract_id, &contract, caller.clone());
 
-        env::log_str(&format!(
-            "Cross-chain HTLC refunded: {}, sender: {}",
-            contract_id, contract.sender
-        ));
+        HtlcEvent::CrossChainPublicWithdrawn {
+            id: contract_id,
+            caller,
+            sender: contract.sender,
+            receiver: contract.receiver,
+            amount: contract.amount,
+            fee,
+            status: contract.status,
+            hashlock: hex::encode(&contract.hashlock),
+            preimage: hex::encode(&preimage.0),
+            bounty: contract.safety_deposit,
+        }
+        .emit();
+    }
+
+    /// Refund a cross-chain HTLC to its sender in the public cancellation
+    /// stage. Callable by anyone, who earns the escrow's safety deposit.
+    pub fn public_cancel(&mut self, contract_id: String) {
+        self.migrate_escrow(&contract_id);
+        let mut contract = self
+            .cross_chain_contracts
+            .record(&contract_id)
+            .expect("Contract does not exist");
+
+        contract.status.transition(EscrowStatus::Refunded);
+        assert_eq!(
+            contract.timelocks.stage(),
+            TimelockStage::PublicCancellation,
+            "Not in the public cancellation stage"
+        );
+        safety_deposit::assert_safety_deposit_locked(&contract);
+
+        self.cross_chain_contracts.insert(&contract_id, &contract);
+
+        let caller = env::predecessor_account_id();
+        self.pay_out(
+            Some(contract_id.clone()),
+            contract.sender.clone(),
+            contract.amount,
+            contract.token.clone(),
+        );
+        self.slash_safety_deposit(&contract_id, &contract, caller.clone());
+
+        HtlcEvent::CrossChainPublicCancelled {
+            id: contract_id,
+            caller,
+            sender: contract.sender,
+            receiver: contract.receiver,
+            amount: contract.amount,
+            status: contract.status,
+            hashlock: hex::encode(&contract.hashlock),
+            bounty: contract.safety_deposit,
+        }
+        .emit();
+    }
+
+    /// Fusion+ stage of a cross-chain HTLC at the current block time
+    pub fn get_current_stage(&self, contract_id: String) -> Option<TimelockStage> {
+        self.cross_chain_contracts
+            .record(&contract_id)
+            .map(|contract| contract.timelocks.stage())
+    }
+
+    /// Get cross-chain contract details
+    pub fn get_cross_chain_contract(&self, contract_id: String) -> Option<CrossChainHTLCView> {
+        self.cross_chain_contracts
+            .record(&contract_id)
+            .map(|contract| CrossChainHTLCView::new(contract_id, contract))
+    }
+
+    pub fn get_cross_chain_contract_count(&self) -> u64 {
+        self.cross_chain_contracts.len()
     }
 
-    /// Get cross-chain contract details (returns tuple instead of struct to avoid JsonSchema requirement)
-    pub fn get_cross_chain_contract(&self, contract_id: String) -> Option<(String, String, String, String, u64, bool, bool, String, Option<String>)> {
-        self.cross_chain_contracts.get(&contract_id).map(|contract| (
-            contract.sender.to_string(),
-            contract.receiver.to_string(),
-            contract.amount.0.to_string(),
-            hex::encode(&contract.hashlock),
-            contract.timelock,
-            contract.withdrawn,
-            contract.refunded,
-            contract.eth_address,
-            contract.eth_tx_hash
-        ))
+    /// List cross-chain HTLCs, paginated and filtered like `get_all_contracts`
+    pub fn get_all_cross_chain_contracts(
+        &self,
+        from_index: Option<u64>,
+        limit: Option<u64>,
+        status: Option<EscrowStatus>,
+    ) -> Vec<CrossChainHTLCView> {
+        let (skip, take) = page_bounds(from_index, limit);
+        self.cross_chain_contracts
+            .records(skip, take)
+            .map(|(id, contract)| CrossChainHTLCView::new(id, contract))
+            .filter(|view| status.is_none_or(|status| view.status == status))
+            .collect()
     }
 
     /// Authorize resolver
     pub fn authorize_resolver(&mut self, resolver: AccountId) {
-        assert_eq!(env::predecessor_account_id(), self.owner, "Only owner");
+        self.assert_role(Role::ResolverManager);
         self.authorized_resolvers.insert(&resolver, &true);
-        env::log_str(&format!("Resolver authorized: {}", resolver));
+        HtlcEvent::ResolverAuthorized { resolver }.emit();
+    }
+
+    /// Revoke resolver
+    pub fn revoke_resolver(&mut self, resolver: AccountId) {
+        self.assert_role(Role::ResolverManager);
+        assert!(
+            self.authorized_resolvers.remove(&resolver).is_some(),
+            "Resolver not authorized"
+        );
+        HtlcEvent::ResolverRevoked { resolver }.emit();
     }
 
     /// Check if resolver is authorized
@@ -413,138 +1027,213 @@ impl HTLCNear {
         self.authorized_resolvers.get(&resolver).unwrap_or(false)
     }
 
-    /// Migrate contract to support partial fills (owner only)
-    pub fn migrate_to_partial_fills(&mut self) {
-        assert_eq!(env::predecessor_account_id(), self.owner, "Only owner can migrate");
-        
-        // Initialize new fields if they don't exist
-        // Note: This is automatically handled by the new() constructor pattern
-        // but we add this method for explicit migration
-        
-        env::log_str("Contract migrated to support partial fills");
+    /// List authorized resolvers, paginated
+    pub fn get_resolvers(&self, from_index: Option<u64>, limit: Option<u64>) -> Vec<AccountId> {
+        let (skip, take) = page_bounds(from_index, limit);
+        self.authorized_resolvers
+            .keys()
+            .skip(skip)
+            .take(take)
+            .collect()
+    }
+
+    /// Let any account create destination escrows and complete partial
+    /// fills, not only authorized resolvers (resolver manager role)
+    pub fn set_permissionless(&mut self, permissionless: bool) {
+        self.assert_role(Role::ResolverManager);
+        self.permissionless = permissionless;
+        HtlcEvent::PermissionlessModeSet { permissionless }.emit();
+    }
+
+    pub fn is_permissionless(&self) -> bool {
+        self.permissionless
     }
 
     // ======= PARTIAL FILLS FOR 1INCH FUSION+ =======
 
-    /// Create initial partial fill swap (main order)
+    /// Create a partial fill swap (main order), locking the attached
+    /// `total_amount` for authorized resolvers to claim in fills unlocked by
+    /// the maker's Merkle `secrets`
+    #[payable]
     pub fn create_partial_fill_swap(
         &mut self,
-        receiver: AccountId,
         total_amount: U128,
         eth_address: String,
         timelock: Timestamp,
+        auction: Option<DutchAuction>,
+        secrets: MerkleSecrets,
     ) -> String {
+        self.assert_not_paused();
+        let initial_storage = env::storage_usage();
         let sender = env::predecessor_account_id();
 
         assert!(total_amount.0 > 0, "Total amount must be greater than 0");
+        assert_eq!(
+            env::attached_deposit().as_yoctonear(),
+            total_amount.0,
+            "Must attach the total amount"
+        );
         assert!(
             timelock > env::block_timestamp_ms(),
             "Timelock must be in the future"
         );
         assert!(!eth_address.is_empty(), "ETH address required");
+        if let Some(auction) = &auction {
+            auction.assert_valid();
+        }
+        secrets.assert_valid();
 
-        let swap_id = format!(
-            "pf-swap-{}-{}-{}",
-            sender,
-            total_amount.0,
-            env::block_timestamp_ms()
+        let swap_id = self.next_escrow_id(EscrowKind::PartialFillSwap);
+        assert!(
+            self.partial_fill_swaps.record(&swap_id).is_none(),
+            "Escrow ID already in use"
         );
 
         let swap = PartialFillSwap {
             swap_id: swap_id.clone(),
-            sender: sender.clone(),
-            receiver,
+            sender,
             total_amount,
             filled_amount: U128(0),
             remaining_amount: total_amount,
             eth_address,
             timelock,
-            completed: false,
+            status: SwapStatus::Open,
             created_at: env::block_timestamp_ms(),
             fill_count: 0,
+            auction,
+            secrets: Some(secrets),
+            last_secret_index: None,
+            open_fill_count: 0,
+            fee_bps: self.fee_bps,
         };
 
         self.partial_fill_swaps.insert(&swap_id, &swap);
+        self.fills_by_swap.insert(
+            &swap_id,
+            &UnorderedSet::new(StorageKey::SwapFills {
+                swap_hash: env::sha256(swap_id.as_bytes()),
+            }),
+        );
+        self.charge_storage(&swap.sender, initial_storage);
 
-        env::log_str(&format!(
-            "Partial Fill Swap created: {}, sender: {}, total: {}",
-            swap_id, sender, total_amount.0
-        ));
+        HtlcEvent::PartialFillSwapCreated {
+            swap_id: swap_id.clone(),
+            sender: swap.sender,
+            total_amount: swap.total_amount,
+            timelock: swap.timelock,
+            eth_address: swap.eth_address,
+            auction: swap.auction,
+            secrets: swap.secrets,
+        }
+        .emit();
 
         swap_id
     }
 
-    /// Create a partial fill (user signs for small amount)
-    #[payable]
+    /// Claim `fill_amount` of a swap's locked balance in a fill, released to
+    /// the calling resolver against the preimage of `hashlock`
     pub fn create_partial_fill(
         &mut self,
         swap_id: String,
         hashlock: Base64VecU8,
         fill_amount: U128,
+        secret: FillSecret,
     ) -> String {
-        let sender = env::predecessor_account_id();
-        let attached_amount = env::attached_deposit();
+        self.assert_not_paused();
+        let initial_storage = env::storage_usage();
+        let resolver = env::predecessor_account_id();
+        self.assert_resolver(&resolver);
 
         // Get the main swap
         let mut swap = self
             .partial_fill_swaps
-            .get(&swap_id)
+            .record(&swap_id)
             .expect("Partial fill swap does not exist");
 
-        assert_eq!(sender, swap.sender, "Only swap sender can create fills");
-        assert!(!swap.completed, "Swap already completed");
+        match swap.status {
+            SwapStatus::Open => {}
+            SwapStatus::Cancelled => env::panic_str("Swap cancelled"),
+            _ => env::panic_str("Swap already fully allocated"),
+        }
+        assert!(
+            env::block_timestamp_ms() <= swap.timelock,
+            "Timelock expired"
+        );
         assert!(fill_amount.0 > 0, "Fill amount must be greater than 0");
         assert!(
             fill_amount.0 <= swap.remaining_amount.0,
             "Fill amount exceeds remaining amount"
         );
-        assert_eq!(
-            attached_amount.as_yoctonear(),
-            fill_amount.0,
-            "Must attach exact fill amount"
-        );
         assert!(!hashlock.0.is_empty(), "Hashlock cannot be empty");
         assert!(hashlock.0.len() == 32, "Hashlock must be 32 bytes");
-
-        let fill_id = format!(
-            "fill-{}-{}-{}",
-            swap_id,
-            fill_amount.0,
-            env::block_timestamp_ms()
+        let (secret_index, hash_algorithm) = merkle::check_fill_secret(
+            &swap,
+            &hashlock.0,
+            swap.filled_amount.0 + fill_amount.0,
+            secret,
         );
 
+        let fill_id = self.next_escrow_id(EscrowKind::PartialFill);
+        assert!(self.partial_fills.record(&fill_id).is_none(), "Escrow ID already in use");
+
         let partial_fill = PartialFill {
             fill_id: fill_id.clone(),
             parent_swap_id: swap_id.clone(),
-            sender: sender.clone(),
-            receiver: swap.receiver.clone(),
+            sender: swap.sender.clone(),
+            receiver: resolver,
             fill_amount,
             hashlock: hashlock.0,
             timelock: swap.timelock,
-            completed: false,
-            refunded: false,
+            status: EscrowStatus::Active,
             eth_address: swap.eth_address.clone(),
             eth_tx_hash: None,
             created_at: env::block_timestamp_ms(),
+            hash_algorithm,
+            min_destination_amount: swap.auction.as_ref().map(|auction| {
+                DutchAuction::destination_amount(
+                    fill_amount,
+                    auction.rate_at(env::block_timestamp_ms()),
+                )
+            }),
+            secret_index: Some(secret_index),
+            fee_bps: swap.fee_bps,
         };
 
         // Update swap state
         swap.filled_amount = U128(swap.filled_amount.0 + fill_amount.0);
         swap.remaining_amount = U128(swap.remaining_amount.0 - fill_amount.0);
         swap.fill_count += 1;
+        swap.open_fill_count += 1;
+        swap.last_secret_index = Some(secret_index);
 
         if swap.remaining_amount.0 == 0 {
-            swap.completed = true;
+            Self::set_swap_status(&mut swap, SwapStatus::FullyAllocated);
         }
 
         // Store updates
         self.partial_fills.insert(&fill_id, &partial_fill);
+        self.index_escrow(&fill_id, &partial_fill.sender, &partial_fill.receiver);
+        let mut swap_fills = self.swap_fill_ids(&swap_id);
+        swap_fills.insert(&fill_id);
+        self.fills_by_swap.insert(&swap_id, &swap_fills);
         self.partial_fill_swaps.insert(&swap_id, &swap);
+        self.charge_storage(&partial_fill.receiver, initial_storage);
 
-        env::log_str(&format!(
-            "Partial Fill created: {}, amount: {}, remaining: {}",
-            fill_id, fill_amount.0, swap.remaining_amount.0
-        ));
+        HtlcEvent::PartialFillCreated {
+            fill_id: fill_id.clone(),
+            swap_id,
+            sender: partial_fill.sender,
+            receiver: partial_fill.receiver,
+            fill_amount: partial_fill.fill_amount,
+            remaining_amount: swap.remaining_amount,
+            hashlock: hex::encode(&partial_fill.hashlock),
+            timelock: partial_fill.timelock,
+            eth_address: partial_fill.eth_address,
+            hash_algorithm: partial_fill.hash_algorithm,
+            min_destination_amount: partial_fill.min_destination_amount,
+            secret_index: partial_fill.secret_index,
+        }
+        .emit();
 
         fill_id
     }
@@ -556,13 +1245,13 @@ impl HTLCNear {
         preimage: Base64VecU8,
         eth_tx_hash: String,
     ) {
+        self.migrate_escrow(&fill_id);
         let mut partial_fill = self
             .partial_fills
-            .get(&fill_id)
+            .record(&fill_id)
             .expect("Partial fill does not exist");
 
-        assert!(!partial_fill.completed, "Fill already completed");
-        assert!(!partial_fill.refunded, "Fill already refunded");
+        partial_fill.status.transition(EscrowStatus::Withdrawn);
         assert!(
             env::predecessor_account_id() == partial_fill.receiver,
             "Only receiver can complete fill"
@@ -573,36 +1262,55 @@ impl HTLCNear {
         );
 
         // Verify preimage
-        let hash = sha2::Sha256::digest(&preimage.0);
-        assert_eq!(
-            hash.as_slice(),
-            &partial_fill.hashlock,
+        assert!(
+            partial_fill.hash_algorithm.matches(&preimage.0, &partial_fill.hashlock),
             "Invalid preimage"
         );
 
-        partial_fill.completed = true;
+        storage::assert_eth_tx_hash(&eth_tx_hash);
         partial_fill.eth_tx_hash = Some(eth_tx_hash.clone());
         self.partial_fills.insert(&fill_id, &partial_fill);
 
-        // Transfer NEAR to receiver
-        Promise::new(partial_fill.receiver.clone())
-            .transfer(NearToken::from_yoctonear(partial_fill.fill_amount.0));
+        let mut swap = self
+            .partial_fill_swaps
+            .record(&partial_fill.parent_swap_id)
+            .expect("Parent swap not found");
+        Self::close_fill(&mut swap);
+        self.partial_fill_swaps.insert(&swap.swap_id, &swap);
+
+        // Transfer NEAR to receiver, less the protocol fee
+        let (payout, fee) = self.take_fee(partial_fill.fill_amount, partial_fill.fee_bps, &None);
+        self.pay_out(
+            Some(fill_id.clone()),
+            partial_fill.receiver.clone(),
+            payout,
+            None,
+        );
 
-        env::log_str(&format!(
-            "Partial Fill completed: {}, receiver: {}, amount: {}, eth_tx: {}",
-            fill_id, partial_fill.receiver, partial_fill.fill_amount.0, eth_tx_hash
-        ));
+        HtlcEvent::PartialFillCompleted {
+            fill_id,
+            swap_id: partial_fill.parent_swap_id,
+            sender: partial_fill.sender,
+            receiver: partial_fill.receiver,
+            fill_amount: partial_fill.fill_amount,
+            fee,
+            status: partial_fill.status,
+            hashlock: hex::encode(&partial_fill.hashlock),
+            preimage: hex::encode(&preimage.0),
+            eth_address: partial_fill.eth_address,
+            eth_tx_hash,
+        }
+        .emit();
     }
 
     /// Refund a partial fill after timelock
     pub fn refund_partial_fill(&mut self, fill_id: String) {
-        let mut partial_fill = self
+        self.migrate_escrow(&fill_id);
+        let partial_fill = self
             .partial_fills
-            .get(&fill_id)
+            .record(&fill_id)
             .expect("Partial fill does not exist");
 
-        assert!(!partial_fill.completed, "Fill already completed");
-        assert!(!partial_fill.refunded, "Fill already refunded");
         assert!(
             env::predecessor_account_id() == partial_fill.sender,
             "Only sender can refund fill"
@@ -612,135 +1320,333 @@ impl HTLCNear {
             "Timelock not expired"
         );
 
-        partial_fill.refunded = true;
-        self.partial_fills.insert(&fill_id, &partial_fill);
-
         // Update parent swap
         let mut swap = self
             .partial_fill_swaps
-            .get(&partial_fill.parent_swap_id)
+            .record(&partial_fill.parent_swap_id)
             .expect("Parent swap not found");
 
-        swap.filled_amount = U128(swap.filled_amount.0 - partial_fill.fill_amount.0);
-        swap.remaining_amount = U128(swap.remaining_amount.0 + partial_fill.fill_amount.0);
-        swap.completed = false; // Reopen swap for more fills
-
-        self.partial_fill_swaps.insert(&partial_fill.parent_swap_id, &swap);
+        let sender = partial_fill.sender.clone();
+        let amount = self.mark_fill_refunded(fill_id.clone(), partial_fill, &mut swap);
+        self.partial_fill_swaps.insert(&swap.swap_id, &swap);
 
         // Refund NEAR to sender
-        Promise::new(partial_fill.sender.clone())
-            .transfer(NearToken::from_yoctonear(partial_fill.fill_amount.0));
-
-        env::log_str(&format!(
-            "Partial Fill refunded: {}, sender: {}, amount: {}",
-            fill_id, partial_fill.sender, partial_fill.fill_amount.0
-        ));
+        self.pay_out(Some(fill_id), sender, U128(amount), None);
     }
 
-    /// Get partial fill swap details
-    pub fn get_partial_fill_swap(&self, swap_id: String) -> Option<(String, String, String, String, String, String, String, u64, bool, u64, u32)> {
-        self.partial_fill_swaps.get(&swap_id).map(|swap| (
-            swap.swap_id,
-            swap.sender.to_string(),
-            swap.receiver.to_string(),
-            swap.total_amount.0.to_string(),
-            swap.filled_amount.0.to_string(),
-            swap.remaining_amount.0.to_string(),
-            swap.eth_address,
-            swap.timelock,
-            swap.completed,
-            swap.created_at,
-            swap.fill_count,
-        ))
-    }
+    /// Refund every expired, unsettled fill among `limit` fills of a swap
+    /// starting at `from_index`, in a single transfer to the swap sender.
+    /// Returns the refunded total.
+    pub fn refund_expired_fills(
+        &mut self,
+        swap_id: String,
+        from_index: Option<u64>,
+        limit: Option<u64>,
+    ) -> U128 {
+        let mut swap = self
+            .partial_fill_swaps
+            .record(&swap_id)
+            .expect("Partial fill swap does not exist");
 
-    /// Get partial fill details
-    pub fn get_partial_fill(&self, fill_id: String) -> Option<(String, String, String, String, String, String, u64, bool, bool, String, Option<String>, u64)> {
-        self.partial_fills.get(&fill_id).map(|fill| (
-            fill.fill_id,
-            fill.parent_swap_id,
-            fill.sender.to_string(),
-            fill.receiver.to_string(),
-            fill.fill_amount.0.to_string(),
-            hex::encode(&fill.hashlock),
-            fill.timelock,
-            fill.completed,
-            fill.refunded,
-            fill.eth_address,
-            fill.eth_tx_hash,
-            fill.created_at,
-        ))
-    }
-
-    /// Get all partial fills for a swap
-    pub fn get_swap_partial_fills(&self, swap_id: String) -> Vec<(String, String, String, String, String, String, u64, bool, bool, String, Option<String>, u64)> {
-        self.partial_fills
-            .iter()
-            .filter(|(_, fill)| fill.parent_swap_id == swap_id)
-            .map(|(_, fill)| (
-                fill.fill_id.clone(),
-                fill.parent_swap_id.clone(),
-                fill.sender.to_string(),
-                fill.receiver.to_string(),
-                fill.fill_amount.0.to_string(),
-                hex::encode(&fill.hashlock),
-                fill.timelock,
-                fill.completed,
-                fill.refunded,
-                fill.eth_address.clone(),
-                fill.eth_tx_hash.clone(),
-                fill.created_at,
-            ))
-            .collect()
-    }
+        assert!(
+            env::predecessor_account_id() == swap.sender,
+            "Only sender can refund fill"
+        );
 
-    /// Get swap progress statistics
-    pub fn get_swap_progress(&self, swap_id: String) -> Option<(String, String, String, u32, bool, u32)> {
-        self.partial_fill_swaps.get(&swap_id).map(|swap| {
-            let fill_percentage = if swap.total_amount.0 > 0 {
-                ((swap.filled_amount.0 * 100) / swap.total_amount.0) as u32
-            } else {
-                0
-            };
-
-            (
-                swap.total_amount.0.to_string(),
-                swap.filled_amount.0.to_string(),
-                swap.remaining_amount.0.to_string(),
-                swap.fill_count,
-                swap.completed,
-                fill_percentage,
-            )
-        })
-    }
-}
+        let (skip, take) = page_bounds(from_index, limit);
+        let fill_ids: Vec<String> = self
+            .swap_fill_ids(&swap_id)
+            .iter()
+            .skip(skip)
+            .take(take)
+            .collect();
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use near_sdk::test_utils::{accounts, VMContextBuilder};
-    use near_sdk::{testing_env, Balance};
+        let mut total = 0;
+        for fill_id in fill_ids {
+            let partial_fill = self
+                .partial_fills
+                .record(&fill_id)
+                .expect("Partial fill does not exist");
+            if partial_fill.status.at(partial_fill.timelock) != EscrowStatus::Expired {
+                continue;
+            }
+            total += self.mark_fill_refunded(fill_id, partial_fill, &mut swap);
+        }
 
-    const ATTACHED_DEPOSIT: Balance = 1_000_000_000_000_000_000_000_000; // 1 NEAR
+        if total > 0 {
+            self.partial_fill_swaps.insert(&swap_id, &swap);
+            self.pay_out(Some(swap_id), swap.sender, U128(total), None);
+        }
 
-    fn get_context(predecessor_account_id: AccountId) -> VMContextBuilder {
-        let mut builder = VMContextBuilder::new();
-        builder
-            .current_account_id(accounts(0))
-            .signer_account_id(predecessor_account_id.clone())
-            .predecessor_account_id(predecessor_account_id);
-        builder
+        U128(total)
+    }
+
+    /// Flag a fill as refunded, release its allocation and emit the refund
+    /// event. The caller persists `swap` and pays out the returned amount.
+    fn mark_fill_refunded(
+        &mut self,
+        fill_id: String,
+        mut partial_fill: PartialFill,
+        swap: &mut PartialFillSwap,
+    ) -> u128 {
+        partial_fill.status.transition(EscrowStatus::Refunded);
+        self.partial_fills.insert(&fill_id, &partial_fill);
+
+        swap.filled_amount = U128(swap.filled_amount.0 - partial_fill.fill_amount.0);
+        Self::close_fill(swap);
+
+        HtlcEvent::PartialFillRefunded {
+            fill_id,
+            swap_id: partial_fill.parent_swap_id,
+            sender: partial_fill.sender,
+            receiver: partial_fill.receiver,
+            fill_amount: partial_fill.fill_amount,
+            remaining_amount: swap.remaining_amount,
+            status: partial_fill.status,
+            hashlock: hex::encode(&partial_fill.hashlock),
+            timelock: partial_fill.timelock,
+        }
+        .emit();
+
+        partial_fill.fill_amount.0
+    }
+
+    /// Return the balance of an expired swap that no fill claimed to its
+    /// sender. Returns the refunded amount.
+    pub fn refund_partial_fill_swap(&mut self, swap_id: String) -> U128 {
+        let mut swap = self
+            .partial_fill_swaps
+            .record(&swap_id)
+            .expect("Partial fill swap does not exist");
+
+        assert!(
+            env::predecessor_account_id() == swap.sender,
+            "Only sender can refund swap"
+        );
+        assert!(
+            env::block_timestamp_ms() > swap.timelock,
+            "Timelock not expired"
+        );
+        assert!(swap.remaining_amount.0 > 0, "Nothing to refund");
+
+        let amount = self.release_swap_remainder(&swap_id, &mut swap);
+        HtlcEvent::PartialFillSwapRefunded {
+            swap_id,
+            sender: swap.sender,
+            amount,
+        }
+        .emit();
+        amount
+    }
+
+    /// Stop new fills of a swap and return its unclaimed balance to the
+    /// sender at once. Existing fills settle or expire on their own.
+    /// Returns the refunded amount.
+    pub fn cancel_partial_fill_swap(&mut self, swap_id: String) -> U128 {
+        let mut swap = self
+            .partial_fill_swaps
+            .record(&swap_id)
+            .expect("Partial fill swap does not exist");
+
+        assert!(
+            env::predecessor_account_id() == swap.sender,
+            "Only sender can cancel swap"
+        );
+        match swap.status {
+            SwapStatus::Open => {}
+            SwapStatus::Cancelled => env::panic_str("Swap already cancelled"),
+            _ => env::panic_str("Swap already fully allocated"),
+        }
+
+        Self::set_swap_status(&mut swap, SwapStatus::Cancelled);
+        let amount = self.release_swap_remainder(&swap_id, &mut swap);
+        HtlcEvent::PartialFillSwapCancelled {
+            swap_id,
+            sender: swap.sender,
+            refunded_amount: amount,
+            filled_amount: swap.filled_amount,
+        }
+        .emit();
+        amount
+    }
+
+    /// Pay the unclaimed balance of `swap` to its sender and persist it.
+    fn release_swap_remainder(&mut self, swap_id: &String, swap: &mut PartialFillSwap) -> U128 {
+        let amount = swap.remaining_amount;
+        swap.remaining_amount = U128(0);
+        Self::settle_swap_if_done(swap);
+        self.partial_fill_swaps.insert(swap_id, swap);
+        if amount.0 > 0 {
+            self.pay_out(Some(swap_id.clone()), swap.sender.clone(), amount, None);
+        }
+        amount
+    }
+
+    /// Move `swap` to `status` and emit the change.
+    fn set_swap_status(swap: &mut PartialFillSwap, status: SwapStatus) {
+        let from = swap.status;
+        swap.status.transition(status);
+        HtlcEvent::SwapStatusChanged {
+            swap_id: swap.swap_id.clone(),
+            from,
+            to: status,
+        }
+        .emit();
+    }
+
+    /// Count one fill of `swap` as completed or refunded.
+    fn close_fill(swap: &mut PartialFillSwap) {
+        swap.open_fill_count -= 1;
+        Self::settle_swap_if_done(swap);
+    }
+
+    /// Settle `swap` once its balance is gone and every fill is settled.
+    /// Cancelled swaps stay cancelled.
+    fn settle_swap_if_done(swap: &mut PartialFillSwap) {
+        if swap.open_fill_count == 0
+            && swap.remaining_amount.0 == 0
+            && matches!(swap.status, SwapStatus::Open | SwapStatus::FullyAllocated)
+        {
+            Self::set_swap_status(swap, SwapStatus::Settled);
+        }
+    }
+
+    fn swap_fill_ids(&self, swap_id: &String) -> UnorderedSet<String> {
+        self.fills_by_swap.get(swap_id).expect("Partial fill swap does not exist")
+    }
+
+    /// Get partial fill swap details
+    pub fn get_partial_fill_swap(&self, swap_id: String) -> Option<PartialFillSwapView> {
+        self.partial_fill_swaps.record(&swap_id).map(PartialFillSwapView::from)
+    }
+
+    /// Get partial fill details
+    pub fn get_partial_fill(&self, fill_id: String) -> Option<PartialFillView> {
+        self.partial_fills.record(&fill_id).map(PartialFillView::from)
+    }
+
+    /// Get the partial fills of a swap, paginated over that swap's fills
+    pub fn get_swap_partial_fills(
+        &self,
+        swap_id: String,
+        from_index: Option<u64>,
+        limit: Option<u64>,
+    ) -> Vec<PartialFillView> {
+        let Some(fill_ids) = self.fills_by_swap.get(&swap_id) else {
+            return vec![];
+        };
+        let (skip, take) = page_bounds(from_index, limit);
+        fill_ids
+            .iter()
+            .skip(skip)
+            .take(take)
+            .filter_map(|fill_id| self.partial_fills.record(&fill_id))
+            .map(PartialFillView::from)
+            .collect()
+    }
+
+    pub fn get_partial_fill_swap_count(&self) -> u64 {
+        self.partial_fill_swaps.len()
+    }
+
+    /// List partial fill swaps, paginated and filtered like `get_all_contracts`
+    pub fn get_all_partial_fill_swaps(
+        &self,
+        from_index: Option<u64>,
+        limit: Option<u64>,
+        status: Option<SwapStatus>,
+    ) -> Vec<PartialFillSwapView> {
+        let (skip, take) = page_bounds(from_index, limit);
+        self.partial_fill_swaps
+            .records(skip, take)
+            .map(|(_, swap)| PartialFillSwapView::from(swap))
+            .filter(|view| status.is_none_or(|status| view.status == status))
+            .collect()
+    }
+
+    pub fn get_partial_fill_count(&self) -> u64 {
+        self.partial_fills.len()
+    }
+
+    /// List partial fills, paginated and filtered like `get_all_contracts`
+    pub fn get_all_partial_fills(
+        &self,
+        from_index: Option<u64>,
+        limit: Option<u64>,
+        status: Option<EscrowStatus>,
+    ) -> Vec<PartialFillView> {
+        let (skip, take) = page_bounds(from_index, limit);
+        self.partial_fills
+            .records(skip, take)
+            .map(|(_, fill)| PartialFillView::from(fill))
+            .filter(|view| status.is_none_or(|status| view.status == status))
+            .collect()
+    }
+
+    /// Get swap progress statistics
+    pub fn get_swap_progress(&self, swap_id: String) -> Option<SwapProgressView> {
+        self.partial_fill_swaps
+            .record(&swap_id)
+            .map(|swap| SwapProgressView::from(&swap))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sha2::Digest;
+    use crate::migration::{HTLCContractV1, HTLCNearV1, PartialFillSwapV1, PartialFillV1};
+    use near_contract_standards::fungible_token::receiver::FungibleTokenReceiver;
+    use near_contract_standards::non_fungible_token::core::NonFungibleTokenReceiver;
+    use near_contract_standards::storage_management::StorageManagement;
+    use near_sdk::test_utils::{accounts, get_logs, VMContextBuilder};
+    use near_sdk::{testing_env, NearToken};
+
+    const ATTACHED_DEPOSIT: NearToken = NearToken::from_near(1);
+
+    // Contract timelocks are in milliseconds, the VM clock is in nanoseconds
+    fn ms_to_ns(ms: u64) -> u64 {
+        ms * 1_000_000
+    }
+
+    // Registers storage balances for the accounts tests create escrows from
+    fn new_contract() -> HTLCNear {
+        let mut contract = HTLCNear::new(accounts(0));
+        for account in [accounts(1), accounts(2), accounts(3)] {
+            contract.storage_accounts.insert(
+                &account,
+                &StorageAccount {
+                    deposit: NearToken::from_near(1),
+                    used_bytes: 0,
+                },
+            );
+        }
+        contract
+    }
+
+    /// Authorize `accounts(2)`, the account tests claim fills with.
+    fn with_resolver(contract: &mut HTLCNear) {
+        contract.authorized_resolvers.insert(&accounts(2), &true);
+    }
+
+    fn get_context(predecessor_account_id: AccountId) -> VMContextBuilder {
+        let mut builder = VMContextBuilder::new();
+        builder
+            .current_account_id(accounts(0))
+            .signer_account_id(predecessor_account_id.clone())
+            .predecessor_account_id(predecessor_account_id);
+        builder
     }
 
     #[test]
     fn test_create_htlc() {
-        let context = get_context(accounts(1));
+        let mut context = get_context(accounts(1));
         testing_env!(context
             .attached_deposit(ATTACHED_DEPOSIT)
-            .block_timestamp(1_000_000)
+            .block_timestamp(ms_to_ns(1_000_000))
             .build());
 
-        let mut contract = HTLCNear::new(accounts(0));
+        let mut contract = new_contract();
         let hashlock = vec![1u8; 32];
         let timelock = 2_000_000; // Future timestamp
 
@@ -749,27 +1655,153 @@ mod tests {
             Base64VecU8(hashlock.clone()),
             timelock,
             "0x1234567890abcdef".to_string(),
+            None,
         );
 
         let htlc = contract.get_contract(contract_id).unwrap();
-        assert_eq!(htlc.0, accounts(1).to_string());
-        assert_eq!(htlc.1, accounts(2).to_string());
-        assert_eq!(htlc.2, ATTACHED_DEPOSIT.to_string());
-        assert_eq!(htlc.3, hex::encode(&hashlock));
-        assert_eq!(htlc.4, timelock);
-        assert!(!htlc.5);
-        assert!(!htlc.6);
+        assert_eq!(htlc.sender, accounts(1));
+        assert_eq!(htlc.receiver, accounts(2));
+        assert_eq!(htlc.amount.0, ATTACHED_DEPOSIT.as_yoctonear());
+        assert_eq!(htlc.hashlock, hex::encode(&hashlock));
+        assert_eq!(htlc.timelock, timelock);
+        assert_eq!(htlc.status, EscrowStatus::Active);
+    }
+
+    fn last_event() -> near_sdk::serde_json::Value {
+        let logs = get_logs();
+        let log = logs.last().expect("No logs emitted");
+        let json = log.strip_prefix("EVENT_JSON:").expect("Not an event log");
+        near_sdk::serde_json::from_str(json).unwrap()
+    }
+
+    /// Swap status changes logged since the last `testing_env!`, as
+    /// `(from, to)` pairs.
+    fn swap_status_changes() -> Vec<(String, String)> {
+        get_logs()
+            .iter()
+            .filter_map(|log| log.strip_prefix("EVENT_JSON:"))
+            .map(|json| near_sdk::serde_json::from_str::<near_sdk::serde_json::Value>(json).unwrap())
+            .filter(|event| event["event"] == "swap_status_changed")
+            .map(|event| {
+                (
+                    event["data"]["from"].as_str().unwrap().to_string(),
+                    event["data"]["to"].as_str().unwrap().to_string(),
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_create_htlc_emits_event() {
+        let mut context = get_context(accounts(1));
+        testing_env!(context
+            .attached_deposit(ATTACHED_DEPOSIT)
+            .block_timestamp(ms_to_ns(1_000_000))
+            .build());
+
+        let mut contract = new_contract();
+        let hashlock = vec![1u8; 32];
+
+        let contract_id = contract.create_cross_chain_htlc(
+            accounts(2),
+            Base64VecU8(hashlock.clone()),
+            2_000_000,
+            "0x1234567890abcdef".to_string(),
+            None,
+            None,
+            None,
+        );
+
+        let event = last_event();
+        assert_eq!(event["standard"], "htlc-near");
+        assert_eq!(event["version"], "1.0.0");
+        assert_eq!(event["event"], "cross_chain_htlc_created");
+        assert_eq!(event["data"]["id"], contract_id);
+        assert_eq!(event["data"]["sender"], accounts(1).to_string());
+        assert_eq!(event["data"]["receiver"], accounts(2).to_string());
+        assert_eq!(event["data"]["amount"], ATTACHED_DEPOSIT.as_yoctonear().to_string());
+        assert_eq!(event["data"]["hashlock"], hex::encode(&hashlock));
+        assert_eq!(event["data"]["timelock"], 2_000_000);
+        assert_eq!(event["data"]["eth_address"], "0x1234567890abcdef");
+    }
+
+    #[test]
+    fn test_complete_cross_chain_swap_emits_preimage() {
+        let mut context = get_context(accounts(1));
+        testing_env!(context
+            .attached_deposit(ATTACHED_DEPOSIT)
+            .block_timestamp(ms_to_ns(1_000_000))
+            .build());
+
+        let mut contract = new_contract();
+        let preimage = b"test_secret";
+        let hashlock = sha2::Sha256::digest(preimage).to_vec();
+
+        let contract_id = contract.create_cross_chain_htlc(
+            accounts(2),
+            Base64VecU8(hashlock),
+            2_000_000,
+            "0x1234567890abcdef".to_string(),
+            None,
+            None,
+            None,
+        );
+
+        let mut context = get_context(accounts(2));
+        testing_env!(context.block_timestamp(ms_to_ns(1_500_000)).build());
+
+        contract.complete_cross_chain_swap(
+            contract_id.clone(),
+            Base64VecU8(preimage.to_vec()),
+            "0xdeadbeef".to_string(),
+        );
+
+        let event = last_event();
+        assert_eq!(event["event"], "cross_chain_swap_completed");
+        assert_eq!(event["data"]["id"], contract_id);
+        assert_eq!(event["data"]["preimage"], hex::encode(preimage));
+        assert_eq!(event["data"]["eth_tx_hash"], "0xdeadbeef");
+        assert_eq!(event["data"]["status"], "withdrawn");
+    }
+
+    #[test]
+    #[should_panic(expected = "ETH transaction hash too long")]
+    fn test_eth_tx_hash_length_capped() {
+        testing_env!(get_context(accounts(1))
+            .attached_deposit(ATTACHED_DEPOSIT)
+            .block_timestamp(ms_to_ns(1_000_000))
+            .build());
+        let mut contract = new_contract();
+        let preimage = b"test_secret";
+        let contract_id = contract.create_cross_chain_htlc(
+            accounts(2),
+            Base64VecU8(sha2::Sha256::digest(preimage).to_vec()),
+            2_000_000,
+            "0x1234567890abcdef".to_string(),
+            None,
+            None,
+            None,
+        );
+
+        testing_env!(get_context(accounts(2))
+            .block_timestamp(ms_to_ns(1_500_000))
+            .build());
+        contract.complete_cross_chain_swap(
+            contract_id,
+            Base64VecU8(preimage.to_vec()),
+            format!("0x{}", "ab".repeat(1_000)),
+        );
     }
 
     #[test]
     fn test_withdraw_with_valid_preimage() {
-        let context = get_context(accounts(1));
+        let mut context = get_context(accounts(1));
         testing_env!(context
             .attached_deposit(ATTACHED_DEPOSIT)
-            .block_timestamp(1_000_000)
+            .block_timestamp(ms_to_ns(1_000_000))
             .build());
 
-        let mut contract = HTLCNear::new(accounts(0));
+        let mut contract = new_contract();
         let preimage = b"test_secret";
         let hash = sha2::Sha256::digest(preimage);
         let hashlock = hash.to_vec();
@@ -780,29 +1812,197 @@ mod tests {
             Base64VecU8(hashlock),
             timelock,
             "0x1234567890abcdef".to_string(),
+            None,
         );
 
         // Switch to receiver
-        let context = get_context(accounts(2));
-        testing_env!(context.block_timestamp(1_500_000).build());
+        let mut context = get_context(accounts(2));
+        testing_env!(context.block_timestamp(ms_to_ns(1_500_000)).build());
 
         contract.withdraw(contract_id.clone(), Base64VecU8(preimage.to_vec()));
 
         let htlc = contract.get_contract(contract_id).unwrap();
-        assert!(htlc.5); // withdrawn
-        assert!(!htlc.6); // refunded
+        assert_eq!(htlc.status, EscrowStatus::Withdrawn);
+    }
+
+    #[test]
+    #[should_panic(expected = "Already withdrawn")]
+    fn test_withdraw_twice() {
+        testing_env!(get_context(accounts(1))
+            .attached_deposit(ATTACHED_DEPOSIT)
+            .block_timestamp(ms_to_ns(1_000_000))
+            .build());
+        let mut contract = new_contract();
+        let preimage = b"test_secret";
+        let contract_id = contract.create_htlc(
+            accounts(2),
+            Base64VecU8(sha2::Sha256::digest(preimage).to_vec()),
+            2_000_000,
+            "0x1234567890abcdef".to_string(),
+            None,
+        );
+
+        testing_env!(get_context(accounts(2))
+            .block_timestamp(ms_to_ns(1_500_000))
+            .build());
+        contract.withdraw(contract_id.clone(), Base64VecU8(preimage.to_vec()));
+        contract.withdraw(contract_id, Base64VecU8(preimage.to_vec()));
+    }
+
+    #[test]
+    fn test_escrow_reported_expired_after_timelock() {
+        testing_env!(get_context(accounts(1))
+            .attached_deposit(ATTACHED_DEPOSIT)
+            .block_timestamp(ms_to_ns(1_000_000))
+            .build());
+        let mut contract = new_contract();
+        let contract_id = contract.create_htlc(
+            accounts(2),
+            Base64VecU8(vec![1u8; 32]),
+            2_000_000,
+            "0x1234567890abcdef".to_string(),
+            None,
+        );
+
+        testing_env!(get_context(accounts(1))
+            .block_timestamp(ms_to_ns(2_500_000))
+            .build());
+        assert_eq!(
+            contract.get_contract(contract_id.clone()).unwrap().status,
+            EscrowStatus::Expired
+        );
+        contract.refund(contract_id.clone());
+        assert_eq!(
+            contract.get_contract(contract_id).unwrap().status,
+            EscrowStatus::Refunded
+        );
+    }
+
+    #[test]
+    fn test_swap_settles_once_fills_complete() {
+        testing_env!(get_context(accounts(1))
+            .attached_deposit(NearToken::from_near(2))
+            .block_timestamp(ms_to_ns(1_000_000))
+            .build());
+        let mut contract = new_contract();
+        with_resolver(&mut contract);
+        let (secrets, fills) = swap_secrets(2);
+        let swap_id = contract.create_partial_fill_swap(
+            U128(2 * ATTACHED_DEPOSIT.as_yoctonear()),
+            "0x1234567890abcdef".to_string(),
+            2_000_000,
+            None,
+            secrets,
+        );
+
+        testing_env!(get_context(accounts(2))
+            .block_timestamp(ms_to_ns(1_000_000))
+            .build());
+        let first = contract.create_partial_fill(
+            swap_id.clone(),
+            fills[0].0.clone(),
+            U128(ATTACHED_DEPOSIT.as_yoctonear()),
+            fills[0].1.clone(),
+        );
+        assert!(swap_status_changes().is_empty());
+        let second = contract.create_partial_fill(
+            swap_id.clone(),
+            fills[2].0.clone(),
+            U128(ATTACHED_DEPOSIT.as_yoctonear()),
+            fills[2].1.clone(),
+        );
+        assert_eq!(
+            swap_status_changes(),
+            vec![("open".to_string(), "fully_allocated".to_string())]
+        );
+        let swap = contract.get_partial_fill_swap(swap_id.clone()).unwrap();
+        assert_eq!(swap.status, SwapStatus::FullyAllocated);
+        assert_eq!(swap.open_fill_count, 2);
+
+        testing_env!(get_context(accounts(2))
+            .block_timestamp(ms_to_ns(1_500_000))
+            .build());
+        contract.complete_partial_fill(first, Base64VecU8(vec![0u8; 32]), "0xabc".to_string());
+        assert!(swap_status_changes().is_empty());
+        contract.complete_partial_fill(second.clone(), Base64VecU8(vec![2u8; 32]), "0xdef".to_string());
+        assert_eq!(
+            swap_status_changes(),
+            vec![("fully_allocated".to_string(), "settled".to_string())]
+        );
+
+        let swap = contract.get_partial_fill_swap(swap_id).unwrap();
+        assert_eq!(swap.status, SwapStatus::Settled);
+        assert_eq!(swap.open_fill_count, 0);
+        assert_eq!(
+            contract.get_partial_fill(second).unwrap().status,
+            EscrowStatus::Withdrawn
+        );
+    }
+
+    #[test]
+    fn test_swap_settles_once_remainder_refunded() {
+        testing_env!(get_context(accounts(1))
+            .attached_deposit(NearToken::from_near(2))
+            .block_timestamp(ms_to_ns(1_000_000))
+            .build());
+        let mut contract = new_contract();
+        with_resolver(&mut contract);
+        let (secrets, fills) = swap_secrets(2);
+        let swap_id = contract.create_partial_fill_swap(
+            U128(2 * ATTACHED_DEPOSIT.as_yoctonear()),
+            "0x1234567890abcdef".to_string(),
+            2_000_000,
+            None,
+            secrets,
+        );
+
+        testing_env!(get_context(accounts(2))
+            .block_timestamp(ms_to_ns(1_000_000))
+            .build());
+        contract.create_partial_fill(
+            swap_id.clone(),
+            fills[0].0.clone(),
+            U128(ATTACHED_DEPOSIT.as_yoctonear()),
+            fills[0].1.clone(),
+        );
+
+        testing_env!(get_context(accounts(1))
+            .block_timestamp(ms_to_ns(2_500_000))
+            .build());
+        assert_eq!(
+            contract.get_partial_fill_swap(swap_id.clone()).unwrap().status,
+            SwapStatus::Expired
+        );
+
+        // The open fill keeps the swap from settling with its remainder
+        contract.refund_partial_fill_swap(swap_id.clone());
+        assert!(swap_status_changes().is_empty());
+        assert_eq!(
+            contract.get_swap_progress(swap_id.clone()).unwrap().status,
+            SwapStatus::Expired
+        );
+
+        contract.refund_expired_fills(swap_id.clone(), None, None);
+        assert_eq!(
+            swap_status_changes(),
+            vec![("open".to_string(), "settled".to_string())]
+        );
+        assert_eq!(
+            contract.get_partial_fill_swap(swap_id).unwrap().status,
+            SwapStatus::Settled
+        );
     }
 
     #[test]
     #[should_panic(expected = "Invalid preimage")]
     fn test_withdraw_with_invalid_preimage() {
-        let context = get_context(accounts(1));
+        let mut context = get_context(accounts(1));
         testing_env!(context
             .attached_deposit(ATTACHED_DEPOSIT)
-            .block_timestamp(1_000_000)
+            .block_timestamp(ms_to_ns(1_000_000))
             .build());
 
-        let mut contract = HTLCNear::new(accounts(0));
+        let mut contract = new_contract();
         let hashlock = vec![1u8; 32];
         let timelock = 2_000_000;
 
@@ -811,11 +2011,12 @@ mod tests {
             Base64VecU8(hashlock),
             timelock,
             "0x1234567890abcdef".to_string(),
+            None,
         );
 
         // Switch to receiver
-        let context = get_context(accounts(2));
-        testing_env!(context.block_timestamp(1_500_000).build());
+        let mut context = get_context(accounts(2));
+        testing_env!(context.block_timestamp(ms_to_ns(1_500_000)).build());
 
         let wrong_preimage = b"wrong_secret";
         contract.withdraw(contract_id, Base64VecU8(wrong_preimage.to_vec()));
@@ -823,13 +2024,13 @@ mod tests {
 
     #[test]
     fn test_refund_after_timelock() {
-        let context = get_context(accounts(1));
+        let mut context = get_context(accounts(1));
         testing_env!(context
             .attached_deposit(ATTACHED_DEPOSIT)
-            .block_timestamp(1_000_000)
+            .block_timestamp(ms_to_ns(1_000_000))
             .build());
 
-        let mut contract = HTLCNear::new(accounts(0));
+        let mut contract = new_contract();
         let hashlock = vec![1u8; 32];
         let timelock = 2_000_000;
 
@@ -838,29 +2039,29 @@ mod tests {
             Base64VecU8(hashlock),
             timelock,
             "0x1234567890abcdef".to_string(),
+            None,
         );
 
         // Move past timelock
-        let context = get_context(accounts(1));
-        testing_env!(context.block_timestamp(2_500_000).build());
+        let mut context = get_context(accounts(1));
+        testing_env!(context.block_timestamp(ms_to_ns(2_500_000)).build());
 
         contract.refund(contract_id.clone());
 
         let htlc = contract.get_contract(contract_id).unwrap();
-        assert!(!htlc.5); // withdrawn
-        assert!(htlc.6); // refunded
+        assert_eq!(htlc.status, EscrowStatus::Refunded);
     }
 
     #[test]
     #[should_panic(expected = "Timelock not expired")]
     fn test_refund_before_timelock() {
-        let context = get_context(accounts(1));
+        let mut context = get_context(accounts(1));
         testing_env!(context
             .attached_deposit(ATTACHED_DEPOSIT)
-            .block_timestamp(1_000_000)
+            .block_timestamp(ms_to_ns(1_000_000))
             .build());
 
-        let mut contract = HTLCNear::new(accounts(0));
+        let mut contract = new_contract();
         let hashlock = vec![1u8; 32];
         let timelock = 2_000_000;
 
@@ -869,24 +2070,25 @@ mod tests {
             Base64VecU8(hashlock),
             timelock,
             "0x1234567890abcdef".to_string(),
+            None,
         );
 
         // Try to refund before timelock
-        let context = get_context(accounts(1));
-        testing_env!(context.block_timestamp(1_500_000).build());
+        let mut context = get_context(accounts(1));
+        testing_env!(context.block_timestamp(ms_to_ns(1_500_000)).build());
 
         contract.refund(contract_id);
     }
 
     #[test]
     fn test_check_preimage() {
-        let context = get_context(accounts(1));
+        let mut context = get_context(accounts(1));
         testing_env!(context
             .attached_deposit(ATTACHED_DEPOSIT)
-            .block_timestamp(1_000_000)
+            .block_timestamp(ms_to_ns(1_000_000))
             .build());
 
-        let mut contract = HTLCNear::new(accounts(0));
+        let mut contract = new_contract();
         let preimage = b"test_secret";
         let hash = sha2::Sha256::digest(preimage);
         let hashlock = hash.to_vec();
@@ -897,6 +2099,7 @@ mod tests {
             Base64VecU8(hashlock),
             timelock,
             "0x1234567890abcdef".to_string(),
+            None,
         );
 
         assert!(contract.check_preimage(contract_id.clone(), Base64VecU8(preimage.to_vec())));
@@ -904,4 +2107,2116 @@ mod tests {
         let wrong_preimage = b"wrong_secret";
         assert!(!contract.check_preimage(contract_id, Base64VecU8(wrong_preimage.to_vec())));
     }
-}
\ No newline at end of file
+
+    #[test]
+    #[should_panic(expected = "Contract is paused")]
+    fn test_pause_blocks_creation() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.block_timestamp(ms_to_ns(1_000_000)).build());
+
+        let mut contract = new_contract();
+        contract.emergency_pause();
+        assert!(contract.is_paused());
+
+        let mut context = get_context(accounts(1));
+        testing_env!(context
+            .attached_deposit(ATTACHED_DEPOSIT)
+            .block_timestamp(ms_to_ns(1_000_000))
+            .build());
+
+        contract.create_htlc(
+            accounts(2),
+            Base64VecU8(vec![1u8; 32]),
+            2_000_000,
+            "0x1234567890abcdef".to_string(),
+            None,
+        );
+    }
+
+    #[test]
+    fn test_refund_while_paused() {
+        let mut context = get_context(accounts(1));
+        testing_env!(context
+            .attached_deposit(ATTACHED_DEPOSIT)
+            .block_timestamp(ms_to_ns(1_000_000))
+            .build());
+
+        let mut contract = new_contract();
+        let contract_id = contract.create_htlc(
+            accounts(2),
+            Base64VecU8(vec![1u8; 32]),
+            2_000_000,
+            "0x1234567890abcdef".to_string(),
+            None,
+        );
+
+        let mut context = get_context(accounts(0));
+        testing_env!(context.block_timestamp(ms_to_ns(1_500_000)).build());
+        contract.emergency_pause();
+
+        let mut context = get_context(accounts(1));
+        testing_env!(context.block_timestamp(ms_to_ns(2_500_000)).build());
+        contract.refund(contract_id.clone());
+        assert_eq!(
+            contract.get_contract(contract_id).unwrap().status,
+            EscrowStatus::Refunded
+        );
+
+        let mut context = get_context(accounts(0));
+        testing_env!(context.block_timestamp(ms_to_ns(2_500_000)).build());
+        contract.unpause();
+        assert!(!contract.is_paused());
+    }
+
+    #[test]
+    fn test_escrow_ids_unique_within_block() {
+        let mut context = get_context(accounts(1));
+        testing_env!(context
+            .attached_deposit(ATTACHED_DEPOSIT)
+            .block_timestamp(ms_to_ns(1_000_000))
+            .build());
+
+        let mut contract = new_contract();
+        let predicted = contract.get_next_escrow_id(EscrowKind::CrossChain);
+
+        let first = contract.create_cross_chain_htlc(
+            accounts(2),
+            Base64VecU8(vec![1u8; 32]),
+            2_000_000,
+            "0x1234567890abcdef".to_string(),
+            None,
+            None,
+            None,
+        );
+        let second = contract.create_cross_chain_htlc(
+            accounts(2),
+            Base64VecU8(vec![1u8; 32]),
+            2_000_000,
+            "0x1234567890abcdef".to_string(),
+            None,
+            None,
+            None,
+        );
+
+        assert_eq!(first, predicted);
+        assert_ne!(first, second);
+        assert!(contract.get_cross_chain_contract(first).is_some());
+        assert!(contract.get_cross_chain_contract(second).is_some());
+    }
+
+    #[test]
+    fn test_views_serialize_named_fields() {
+        let mut context = get_context(accounts(1));
+        testing_env!(context
+            .attached_deposit(ATTACHED_DEPOSIT)
+            .block_timestamp(ms_to_ns(1_000_000))
+            .build());
+
+        let mut contract = new_contract();
+        let contract_id = contract.create_cross_chain_htlc(
+            accounts(2),
+            Base64VecU8(vec![1u8; 32]),
+            2_000_000,
+            "0x1234567890abcdef".to_string(),
+            None,
+            None,
+            None,
+        );
+
+        let view = contract.get_cross_chain_contract(contract_id.clone()).unwrap();
+        let json = near_sdk::serde_json::to_value(view).unwrap();
+        assert_eq!(json["id"], contract_id);
+        assert_eq!(json["sender"], accounts(1).to_string());
+        assert_eq!(json["amount"], ATTACHED_DEPOSIT.as_yoctonear().to_string());
+        assert_eq!(json["hashlock"], hex::encode([1u8; 32]));
+        assert_eq!(json["status"], "active");
+        assert!(json["eth_tx_hash"].is_null());
+    }
+
+    #[test]
+    fn test_get_all_contracts_paginated_by_status() {
+        let mut context = get_context(accounts(1));
+        testing_env!(context
+            .attached_deposit(ATTACHED_DEPOSIT)
+            .block_timestamp(ms_to_ns(1_000_000))
+            .build());
+
+        let mut contract = new_contract();
+        let ids: Vec<String> = (0..3)
+            .map(|i| {
+                contract.create_htlc(
+                    accounts(2),
+                    Base64VecU8(vec![1u8; 32]),
+                    2_000_000 + i * 1_000_000,
+                    "0x1234567890abcdef".to_string(),
+                    None,
+                )
+            })
+            .collect();
+
+        // Only the first HTLC has expired and been refunded
+        let mut context = get_context(accounts(1));
+        testing_env!(context.block_timestamp(ms_to_ns(2_500_000)).build());
+        contract.refund(ids[0].clone());
+
+        let page = contract.get_all_contracts(Some(1), Some(1), None);
+        assert_eq!(page.len(), 1);
+        assert_eq!(page[0].id, ids[1]);
+
+        let active = contract.get_all_contracts(None, None, Some(EscrowStatus::Active));
+        assert_eq!(active.len(), 2);
+        let refunded = contract.get_all_contracts(None, None, Some(EscrowStatus::Refunded));
+        assert_eq!(refunded.len(), 1);
+        assert_eq!(refunded[0].id, ids[0]);
+
+        let mut context = get_context(accounts(1));
+        testing_env!(context.block_timestamp(ms_to_ns(3_500_000)).build());
+        let expired = contract.get_all_contracts(None, None, Some(EscrowStatus::Expired));
+        assert_eq!(expired.len(), 1);
+        assert_eq!(expired[0].id, ids[1]);
+    }
+
+    #[test]
+    fn test_escrows_by_sender_and_receiver() {
+        let mut context = get_context(accounts(1));
+        testing_env!(context
+            .attached_deposit(ATTACHED_DEPOSIT)
+            .block_timestamp(ms_to_ns(1_000_000))
+            .build());
+
+        let mut contract = new_contract();
+        let htlc_id = contract.create_htlc(
+            accounts(2),
+            Base64VecU8(vec![1u8; 32]),
+            2_000_000,
+            "0x1234567890abcdef".to_string(),
+            None,
+        );
+        let cc_id = contract.create_cross_chain_htlc(
+            accounts(3),
+            Base64VecU8(vec![1u8; 32]),
+            2_000_000,
+            "0x1234567890abcdef".to_string(),
+            None,
+            None,
+            None,
+        );
+
+        let sent = contract.get_escrows_by_sender(accounts(1), None, None);
+        assert_eq!(sent.len(), 2);
+        assert!(matches!(&sent[0], EscrowView::Htlc(view) if view.id == htlc_id));
+        assert!(matches!(&sent[1], EscrowView::CrossChain(view) if view.id == cc_id));
+
+        let second_page = contract.get_escrows_by_sender(accounts(1), Some(1), Some(1));
+        assert_eq!(second_page.len(), 1);
+
+        let received = contract.get_escrows_by_receiver(accounts(3), None, None);
+        assert_eq!(received.len(), 1);
+        assert!(matches!(&received[0], EscrowView::CrossChain(view) if view.id == cc_id));
+
+        assert!(contract.get_escrows_by_receiver(accounts(4), None, None).is_empty());
+    }
+
+    #[test]
+    fn test_swap_fills_indexed_and_bulk_refunded() {
+        testing_env!(get_context(accounts(1))
+            .attached_deposit(NearToken::from_near(3))
+            .block_timestamp(ms_to_ns(1_000_000))
+            .build());
+        let mut contract = new_contract();
+        with_resolver(&mut contract);
+        let (secrets, fills) = swap_secrets(3);
+        let swap_id = contract.create_partial_fill_swap(
+            U128(3 * ATTACHED_DEPOSIT.as_yoctonear()),
+            "0x1234567890abcdef".to_string(),
+            2_000_000,
+            None,
+            secrets,
+        );
+
+        testing_env!(get_context(accounts(1))
+            .attached_deposit(ATTACHED_DEPOSIT)
+            .block_timestamp(ms_to_ns(1_000_000))
+            .build());
+        let (other_secrets, other_fills) = swap_secrets(1);
+        let other_swap_id = contract.create_partial_fill_swap(
+            U128(ATTACHED_DEPOSIT.as_yoctonear()),
+            "0x1234567890abcdef".to_string(),
+            2_000_000,
+            None,
+            other_secrets,
+        );
+
+        // A resolver claims fills against the locked balances
+        testing_env!(get_context(accounts(2))
+            .block_timestamp(ms_to_ns(1_000_000))
+            .build());
+        for (hashlock, secret) in &fills[..2] {
+            contract.create_partial_fill(
+                swap_id.clone(),
+                hashlock.clone(),
+                U128(ATTACHED_DEPOSIT.as_yoctonear()),
+                secret.clone(),
+            );
+        }
+        contract.create_partial_fill(
+            other_swap_id.clone(),
+            other_fills[1].0.clone(),
+            U128(ATTACHED_DEPOSIT.as_yoctonear()),
+            other_fills[1].1.clone(),
+        );
+
+        let fills = contract.get_swap_partial_fills(swap_id.clone(), None, None);
+        assert_eq!(fills.len(), 2);
+        assert!(fills.iter().all(|fill| fill.parent_swap_id == swap_id));
+
+        let mut context = get_context(accounts(1));
+        testing_env!(context.block_timestamp(ms_to_ns(2_500_000)).build());
+        let refunded = contract.refund_expired_fills(swap_id.clone(), None, None);
+        assert_eq!(refunded.0, 2 * ATTACHED_DEPOSIT.as_yoctonear());
+
+        let progress = contract.get_swap_progress(swap_id.clone()).unwrap();
+        assert_eq!(progress.filled_amount.0, 0);
+        assert!(contract
+            .get_swap_partial_fills(swap_id.clone(), None, None)
+            .iter()
+            .all(|fill| fill.status == EscrowStatus::Refunded));
+
+        // The unclaimed remainder goes back to the maker
+        let refunded = contract.refund_partial_fill_swap(swap_id.clone());
+        assert_eq!(refunded.0, ATTACHED_DEPOSIT.as_yoctonear());
+        assert_eq!(last_event()["event"], "partial_fill_swap_refunded");
+        assert_eq!(
+            contract.get_swap_progress(swap_id).unwrap().remaining_amount.0,
+            0
+        );
+        assert_eq!(
+            contract.get_swap_partial_fills(other_swap_id, None, None)[0].status,
+            EscrowStatus::Expired
+        );
+    }
+
+    #[test]
+    fn test_storage_charged_and_withdrawn() {
+        let mut context = get_context(accounts(4));
+        testing_env!(context
+            .attached_deposit(NearToken::from_millinear(100))
+            .block_timestamp(ms_to_ns(1_000_000))
+            .build());
+
+        let mut contract = HTLCNear::new(accounts(0));
+        let balance = contract.storage_deposit(None, None);
+        assert_eq!(balance.total, NearToken::from_millinear(100));
+        let available = balance.available;
+
+        let mut context = get_context(accounts(4));
+        testing_env!(context
+            .attached_deposit(ATTACHED_DEPOSIT)
+            .block_timestamp(ms_to_ns(1_000_000))
+            .build());
+        contract.create_cross_chain_htlc(
+            accounts(2),
+            Base64VecU8(vec![1u8; 32]),
+            2_000_000,
+            "0x1234567890abcdef".to_string(),
+            None,
+            None,
+            None,
+        );
+
+        let balance = contract.storage_balance_of(accounts(4)).unwrap();
+        assert!(balance.available < available);
+
+        let mut context = get_context(accounts(4));
+        testing_env!(context
+            .attached_deposit(NearToken::from_yoctonear(1))
+            .build());
+        let balance = contract.storage_withdraw(None);
+        assert_eq!(balance.available, NearToken::from_yoctonear(0));
+    }
+
+    #[test]
+    #[should_panic(expected = "Account has no storage balance")]
+    fn test_create_without_storage_deposit() {
+        let mut context = get_context(accounts(4));
+        testing_env!(context
+            .attached_deposit(ATTACHED_DEPOSIT)
+            .block_timestamp(ms_to_ns(1_000_000))
+            .build());
+
+        let mut contract = HTLCNear::new(accounts(0));
+        contract.create_htlc(
+            accounts(2),
+            Base64VecU8(vec![1u8; 32]),
+            2_000_000,
+            "0x1234567890abcdef".to_string(),
+            None,
+        );
+    }
+
+    #[test]
+    fn test_cleanup_archives_settled_escrow() {
+        let mut context = get_context(accounts(1));
+        testing_env!(context
+            .attached_deposit(ATTACHED_DEPOSIT)
+            .block_timestamp(ms_to_ns(1_000_000))
+            .build());
+
+        let mut contract = new_contract();
+        let hashlock = vec![1u8; 32];
+        let refunded_id = contract.create_htlc(
+            accounts(2),
+            Base64VecU8(hashlock.clone()),
+            2_000_000,
+            "0x1234567890abcdef".to_string(),
+            None,
+        );
+        let open_id = contract.create_htlc(
+            accounts(2),
+            Base64VecU8(hashlock.clone()),
+            2_000_000,
+            "0x1234567890abcdef".to_string(),
+            None,
+        );
+        let used_bytes = contract.storage_accounts.get(&accounts(1)).unwrap().used_bytes;
+
+        let mut context = get_context(accounts(1));
+        testing_env!(context.block_timestamp(ms_to_ns(2_500_000)).build());
+        contract.refund(refunded_id.clone());
+
+        // Still inside the retention period
+        let ids = vec![refunded_id.clone(), open_id.clone()];
+        assert_eq!(contract.cleanup_settled_escrows(ids.clone()), 0);
+
+        let mut context = get_context(accounts(3));
+        testing_env!(context
+            .block_timestamp(ms_to_ns(2_000_001 + contract.get_retention_period()))
+            .build());
+        assert_eq!(contract.cleanup_settled_escrows(ids), 1);
+
+        assert!(contract.get_contract(refunded_id.clone()).is_none());
+        assert!(contract.get_contract(open_id).is_some());
+        let archived = contract.get_archived_escrow(refunded_id).unwrap();
+        assert_eq!(archived.status, EscrowStatus::Refunded);
+        assert_eq!(archived.hashlock, hex::encode(&hashlock));
+        assert_eq!(contract.get_escrows_by_sender(accounts(1), None, None).len(), 1);
+        assert!(contract.storage_accounts.get(&accounts(1)).unwrap().used_bytes < used_bytes);
+    }
+
+    #[test]
+    fn test_cleanup_credits_fill_storage_to_resolver() {
+        testing_env!(get_context(accounts(1))
+            .attached_deposit(ATTACHED_DEPOSIT)
+            .block_timestamp(ms_to_ns(1_000_000))
+            .build());
+        let mut contract = new_contract();
+        with_resolver(&mut contract);
+        let (secrets, fills) = swap_secrets(1);
+        let swap_id = contract.create_partial_fill_swap(
+            U128(ATTACHED_DEPOSIT.as_yoctonear()),
+            "0x1234567890abcdef".to_string(),
+            2_000_000,
+            None,
+            secrets,
+        );
+        let maker_bytes = contract.storage_accounts.get(&accounts(1)).unwrap().used_bytes;
+
+        testing_env!(get_context(accounts(2))
+            .block_timestamp(ms_to_ns(1_000_000))
+            .build());
+        let fill_id = contract.create_partial_fill(
+            swap_id,
+            fills[1].0.clone(),
+            U128(ATTACHED_DEPOSIT.as_yoctonear()),
+            fills[1].1.clone(),
+        );
+        contract.complete_partial_fill(
+            fill_id.clone(),
+            Base64VecU8(vec![1u8; 32]),
+            "0xabc".to_string(),
+        );
+        let resolver_bytes = contract.storage_accounts.get(&accounts(2)).unwrap().used_bytes;
+
+        testing_env!(get_context(accounts(3))
+            .block_timestamp(ms_to_ns(2_000_001 + contract.get_retention_period()))
+            .build());
+        assert_eq!(contract.cleanup_settled_escrows(vec![fill_id.clone()]), 1);
+        assert!(contract.get_partial_fill(fill_id).is_none());
+
+        // The resolver paid for the fill, not the maker
+        assert!(contract.storage_accounts.get(&accounts(2)).unwrap().used_bytes < resolver_bytes);
+        assert_eq!(
+            contract.storage_accounts.get(&accounts(1)).unwrap().used_bytes,
+            maker_bytes
+        );
+    }
+
+    #[test]
+    fn test_cleanup_removes_swap_after_its_fills() {
+        testing_env!(get_context(accounts(1))
+            .attached_deposit(NearToken::from_near(2))
+            .block_timestamp(ms_to_ns(1_000_000))
+            .build());
+        let mut contract = new_contract();
+        with_resolver(&mut contract);
+        let maker_bytes = contract.storage_accounts.get(&accounts(1)).unwrap().used_bytes;
+        let (secrets, fills) = swap_secrets(2);
+        let swap_id = contract.create_partial_fill_swap(
+            U128(2 * ATTACHED_DEPOSIT.as_yoctonear()),
+            "0x1234567890abcdef".to_string(),
+            2_000_000,
+            None,
+            secrets,
+        );
+
+        testing_env!(get_context(accounts(2))
+            .block_timestamp(ms_to_ns(1_000_000))
+            .build());
+        let fill_id = contract.create_partial_fill(
+            swap_id.clone(),
+            fills[0].0.clone(),
+            U128(ATTACHED_DEPOSIT.as_yoctonear()),
+            fills[0].1.clone(),
+        );
+
+        testing_env!(get_context(accounts(1))
+            .block_timestamp(ms_to_ns(1_100_000))
+            .build());
+        contract.cancel_partial_fill_swap(swap_id.clone());
+
+        // Kept while the fill is open
+        testing_env!(get_context(accounts(3))
+            .block_timestamp(ms_to_ns(2_000_001 + contract.get_retention_period()))
+            .build());
+        assert_eq!(contract.cleanup_settled_escrows(vec![swap_id.clone()]), 0);
+
+        testing_env!(get_context(accounts(2))
+            .block_timestamp(ms_to_ns(1_200_000))
+            .build());
+        contract.complete_partial_fill(
+            fill_id.clone(),
+            Base64VecU8(vec![0u8; 32]),
+            "0xabc".to_string(),
+        );
+
+        // And while the settled fill is still listed under it
+        testing_env!(get_context(accounts(3))
+            .block_timestamp(ms_to_ns(2_000_001 + contract.get_retention_period()))
+            .build());
+        assert_eq!(contract.cleanup_settled_escrows(vec![swap_id.clone()]), 0);
+        assert_eq!(
+            contract.cleanup_settled_escrows(vec![fill_id, swap_id.clone()]),
+            2
+        );
+        assert_eq!(last_event()["event"], "partial_fill_swap_archived");
+        assert_eq!(last_event()["data"]["status"], "cancelled");
+        assert!(contract.get_partial_fill_swap(swap_id).is_none());
+        assert_eq!(
+            contract.storage_accounts.get(&accounts(1)).unwrap().used_bytes,
+            maker_bytes
+        );
+    }
+
+    #[test]
+    fn test_migrate_from_v1_state() {
+        let context = get_context(accounts(0));
+        testing_env!(context.build());
+
+        // Layout and ID format of the original deployment
+        let mut old = HTLCNearV1 {
+            contracts: UnorderedMap::new(b"c"),
+            cross_chain_contracts: UnorderedMap::new(b"cc".as_slice()),
+            partial_fill_swaps: UnorderedMap::new(b"s".as_slice()),
+            partial_fills: UnorderedMap::new(b"f".as_slice()),
+            owner: accounts(0),
+            authorized_resolvers: UnorderedMap::new(b"r"),
+        };
+        let htlc_id = format!("{}-{}-1000-0", accounts(1), accounts(2));
+        old.contracts.insert(
+            &htlc_id,
+            &HTLCContractV1 {
+                sender: accounts(1),
+                receiver: accounts(2),
+                amount: U128(1000),
+                hashlock: vec![1u8; 32],
+                timelock: 2_000_000,
+                withdrawn: false,
+                refunded: false,
+                eth_address: "0x1234567890abcdef".to_string(),
+            },
+        );
+        let swap_id = format!("swap-{}-{}-5000-0", accounts(1), accounts(2));
+        old.partial_fill_swaps.insert(
+            &swap_id,
+            &PartialFillSwapV1 {
+                swap_id: swap_id.clone(),
+                sender: accounts(1),
+                receiver: accounts(2),
+                total_amount: U128(5000),
+                filled_amount: U128(2000),
+                remaining_amount: U128(3000),
+                eth_address: "0x1234567890abcdef".to_string(),
+                timelock: 2_000_000,
+                completed: false,
+                created_at: 0,
+                fill_count: 1,
+            },
+        );
+        let fill_id = format!("fill-{}-1-0", swap_id);
+        old.partial_fills.insert(
+            &fill_id,
+            &PartialFillV1 {
+                fill_id: fill_id.clone(),
+                parent_swap_id: swap_id.clone(),
+                sender: accounts(1),
+                receiver: accounts(3),
+                fill_amount: U128(2000),
+                hashlock: vec![2u8; 32],
+                timelock: 2_000_000,
+                completed: false,
+                refunded: false,
+                eth_address: "0x1234567890abcdef".to_string(),
+                eth_tx_hash: None,
+                created_at: 0,
+            },
+        );
+        old.authorized_resolvers.insert(&accounts(3), &true);
+        env::state_write(&old);
+
+        let mut contract = HTLCNear::migrate();
+        assert_eq!(contract.get_state_version(), 2);
+        assert!(contract.is_paused());
+        assert!(contract.is_migration_pending());
+
+        // One record per call
+        let mut calls = 0;
+        while !contract.migrate_records(Some(1)) {
+            calls += 1;
+        }
+        assert_eq!(calls, 2);
+        assert!(!contract.is_migration_pending());
+        contract.unpause();
+
+        let htlc = contract.get_contract(htlc_id.clone()).unwrap();
+        assert_eq!(htlc.amount, U128(1000));
+        assert_eq!(htlc.hashlock, hex::encode([1u8; 32]));
+        assert!(contract.is_authorized_resolver(accounts(3)));
+        assert_eq!(contract.get_swap_progress(swap_id.clone()).unwrap().fill_percentage, 40);
+        assert_eq!(htlc.status, EscrowStatus::Active);
+        let swap = contract.get_partial_fill_swap(swap_id.clone()).unwrap();
+        assert_eq!(swap.status, SwapStatus::FullyAllocated);
+        assert_eq!(swap.open_fill_count, 1);
+        assert_eq!(contract.get_escrows_by_sender(accounts(1), None, None).len(), 2);
+        assert_eq!(contract.get_escrows_by_receiver(accounts(3), None, None).len(), 1);
+        let fills = contract.get_swap_partial_fills(swap_id, None, None);
+        assert_eq!(fills.len(), 1);
+        assert_eq!(fills[0].fill_id, fill_id);
+
+        // New IDs keep working next to the legacy ones
+        assert_eq!(contract.get_next_escrow_id(EscrowKind::Htlc), "htlc-0");
+
+        // Migrating current state again is a no-op
+        env::state_write(&contract);
+        let contract = HTLCNear::migrate();
+        assert!(!contract.is_migration_pending());
+        assert_eq!(contract.get_contract_count(), 1);
+    }
+
+    #[test]
+    #[should_panic(expected = "Record migration still in progress")]
+    fn test_unpause_before_records_migrated() {
+        let context = get_context(accounts(0));
+        testing_env!(context.build());
+
+        let mut old = HTLCNearV1 {
+            contracts: UnorderedMap::new(b"c"),
+            cross_chain_contracts: UnorderedMap::new(b"cc".as_slice()),
+            partial_fill_swaps: UnorderedMap::new(b"s".as_slice()),
+            partial_fills: UnorderedMap::new(b"f".as_slice()),
+            owner: accounts(0),
+            authorized_resolvers: UnorderedMap::new(b"r"),
+        };
+        old.authorized_resolvers.insert(&accounts(3), &true);
+        env::state_write(&old);
+
+        let mut contract = HTLCNear::migrate();
+        contract.unpause();
+    }
+
+    #[test]
+    fn test_settle_v1_records_before_migrated() {
+        testing_env!(get_context(accounts(0)).build());
+        let mut old = HTLCNearV1 {
+            contracts: UnorderedMap::new(b"c"),
+            cross_chain_contracts: UnorderedMap::new(b"cc".as_slice()),
+            partial_fill_swaps: UnorderedMap::new(b"s".as_slice()),
+            partial_fills: UnorderedMap::new(b"f".as_slice()),
+            owner: accounts(0),
+            authorized_resolvers: UnorderedMap::new(b"r"),
+        };
+        let htlc_id = format!("{}-{}-1000-0", accounts(1), accounts(2));
+        old.contracts.insert(
+            &htlc_id,
+            &HTLCContractV1 {
+                sender: accounts(1),
+                receiver: accounts(2),
+                amount: U128(1000),
+                hashlock: sha2::Sha256::digest(b"htlc_secret").to_vec(),
+                timelock: 2_000_000,
+                withdrawn: false,
+                refunded: false,
+                eth_address: "0x1234567890abcdef".to_string(),
+            },
+        );
+        let swap_id = format!("swap-{}-{}-2000-0", accounts(1), accounts(2));
+        old.partial_fill_swaps.insert(
+            &swap_id,
+            &PartialFillSwapV1 {
+                swap_id: swap_id.clone(),
+                sender: accounts(1),
+                receiver: accounts(2),
+                total_amount: U128(2000),
+                filled_amount: U128(2000),
+                remaining_amount: U128(0),
+                eth_address: "0x1234567890abcdef".to_string(),
+                timelock: 2_000_000,
+                completed: true,
+                created_at: 0,
+                fill_count: 1,
+            },
+        );
+        let fill_id = format!("fill-{}-1-0", swap_id);
+        old.partial_fills.insert(
+            &fill_id,
+            &PartialFillV1 {
+                fill_id: fill_id.clone(),
+                parent_swap_id: swap_id.clone(),
+                sender: accounts(1),
+                receiver: accounts(3),
+                fill_amount: U128(2000),
+                hashlock: sha2::Sha256::digest(b"fill_secret").to_vec(),
+                timelock: 2_000_000,
+                completed: false,
+                refunded: false,
+                eth_address: "0x1234567890abcdef".to_string(),
+                eth_tx_hash: None,
+                created_at: 0,
+            },
+        );
+        old.authorized_resolvers.insert(&accounts(3), &true);
+        env::state_write(&old);
+        let mut contract = HTLCNear::migrate();
+
+        // Records not rewritten yet still read and settle
+        testing_env!(get_context(accounts(2))
+            .block_timestamp(ms_to_ns(1_000_000))
+            .build());
+        assert_eq!(
+            contract.get_all_contracts(None, None, Some(EscrowStatus::Active)).len(),
+            1
+        );
+        contract.withdraw(htlc_id.clone(), Base64VecU8(b"htlc_secret".to_vec()));
+        assert_eq!(last_event()["data"]["status"], "withdrawn");
+
+        testing_env!(get_context(accounts(3))
+            .block_timestamp(ms_to_ns(1_000_000))
+            .build());
+        contract.complete_partial_fill(
+            fill_id.clone(),
+            Base64VecU8(b"fill_secret".to_vec()),
+            "0xabc".to_string(),
+        );
+
+        testing_env!(get_context(accounts(0)).build());
+        assert!(contract.migrate_records(None));
+        assert_eq!(
+            contract.get_contract(htlc_id).unwrap().status,
+            EscrowStatus::Withdrawn
+        );
+        assert_eq!(
+            contract.get_partial_fill(fill_id).unwrap().status,
+            EscrowStatus::Withdrawn
+        );
+        let swap = contract.get_partial_fill_swap(swap_id.clone()).unwrap();
+        assert_eq!(swap.status, SwapStatus::Settled);
+        assert_eq!(swap.open_fill_count, 0);
+        assert_eq!(contract.get_swap_partial_fills(swap_id, None, None).len(), 1);
+        assert_eq!(contract.get_escrows_by_sender(accounts(1), None, None).len(), 2);
+    }
+
+    #[test]
+    fn test_failed_payout_becomes_claimable() {
+        let mut contract = new_contract();
+        let preimage = b"secret".to_vec();
+        let hashlock = sha2::Sha256::digest(&preimage).to_vec();
+
+        let mut context = get_context(accounts(1));
+        testing_env!(context.attached_deposit(ATTACHED_DEPOSIT).build());
+        let contract_id = contract.create_htlc(
+            accounts(2),
+            Base64VecU8(hashlock),
+            2_000_000,
+            "0x1234567890abcdef".to_string(),
+            None,
+        );
+
+        let context = get_context(accounts(2));
+        testing_env!(context.build());
+        contract.withdraw(contract_id.clone(), Base64VecU8(preimage));
+
+        // The transfer to the receiver failed
+        let context = get_context(accounts(0));
+        testing_env!(
+            context.build(),
+            near_sdk::test_vm_config(),
+            near_sdk::RuntimeFeesConfig::test(),
+            Default::default(),
+            vec![near_sdk::PromiseResult::Failed],
+        );
+        let amount = U128(ATTACHED_DEPOSIT.as_yoctonear());
+        assert!(!contract.on_payout_complete(Some(contract_id.clone()), accounts(2), amount, None));
+        assert_eq!(last_event()["event"], "payout_failed");
+        assert_eq!(contract.get_claimable_balance(accounts(2), None), amount);
+        assert_eq!(
+            contract.get_contract(contract_id).unwrap().status,
+            EscrowStatus::Withdrawn
+        );
+
+        let context = get_context(accounts(2));
+        testing_env!(context.build());
+        assert_eq!(contract.claim_payout(None), amount);
+        assert_eq!(contract.get_claimable_balance(accounts(2), None), U128(0));
+
+        // The retried transfer went through
+        let context = get_context(accounts(0));
+        testing_env!(
+            context.build(),
+            near_sdk::test_vm_config(),
+            near_sdk::RuntimeFeesConfig::test(),
+            Default::default(),
+            vec![near_sdk::PromiseResult::Successful(vec![])],
+        );
+        assert!(contract.on_payout_complete(None, accounts(2), amount, None));
+        assert_eq!(last_event()["event"], "payout_completed");
+        assert_eq!(contract.get_claimable_balance(accounts(2), None), U128(0));
+    }
+
+    /// Have `account_id` trust the token contract `contract_id`.
+    fn trust_token_contract(contract: &mut HTLCNear, account_id: AccountId, contract_id: AccountId) {
+        testing_env!(get_context(account_id)
+            .attached_deposit(NearToken::from_yoctonear(1))
+            .build());
+        contract.trust_token_contract(contract_id);
+    }
+
+    #[test]
+    fn test_fungible_token_htlc() {
+        let mut contract = new_contract();
+        trust_token_contract(&mut contract, accounts(1), accounts(4));
+        let preimage = b"secret".to_vec();
+        let hashlock = sha2::Sha256::digest(&preimage).to_vec();
+        let msg = near_sdk::serde_json::json!({
+            "kind": "htlc",
+            "receiver": accounts(2),
+            "hashlock": Base64VecU8(hashlock.clone()),
+            "timelock": 2_000_000,
+            "eth_address": "0x1234567890abcdef",
+        });
+
+        // ft_transfer_call from accounts(1) on the token contract accounts(4)
+        let context = get_context(accounts(4));
+        testing_env!(context.build());
+        let unused = contract.ft_on_transfer(accounts(1), U128(500), msg.to_string());
+        assert!(matches!(unused, near_sdk::PromiseOrValue::Value(U128(0))));
+
+        let contract_id = last_event()["data"]["id"].as_str().unwrap().to_string();
+        let htlc = contract.get_contract(contract_id.clone()).unwrap();
+        assert_eq!(htlc.sender, accounts(1));
+        assert_eq!(htlc.amount, U128(500));
+        assert_eq!(htlc.token, Some(accounts(4)));
+
+        let context = get_context(accounts(2));
+        testing_env!(context.build());
+        contract.withdraw(contract_id, Base64VecU8(preimage));
+
+        // Paid out with ft_transfer on the token contract
+        let receipts = near_sdk::test_utils::get_created_receipts();
+        assert_eq!(receipts[0].receiver_id, accounts(4));
+        assert!(matches!(
+            &receipts[0].actions[0],
+            near_sdk::mock::MockAction::FunctionCallWeight { method_name, .. }
+                if method_name == b"ft_transfer"
+        ));
+    }
+
+    #[test]
+    #[should_panic(expected = "Token contract not trusted by the sender")]
+    fn test_fungible_token_from_untrusted_contract() {
+        let mut contract = new_contract();
+        trust_token_contract(&mut contract, accounts(1), accounts(4));
+        let msg = near_sdk::serde_json::json!({
+            "kind": "htlc",
+            "receiver": accounts(2),
+            "hashlock": Base64VecU8(vec![1u8; 32]),
+            "timelock": 2_000_000,
+            "eth_address": "0x1234567890abcdef",
+        });
+
+        // Another contract claiming a transfer from accounts(1)
+        testing_env!(get_context(accounts(5)).build());
+        contract.ft_on_transfer(accounts(1), U128(500), msg.to_string());
+    }
+
+    #[test]
+    #[should_panic(expected = "Invalid escrow message")]
+    fn test_fungible_token_invalid_msg() {
+        let mut contract = new_contract();
+        let context = get_context(accounts(4));
+        testing_env!(context.build());
+        contract.ft_on_transfer(accounts(1), U128(500), "htlc".to_string());
+    }
+
+    #[test]
+    fn test_nft_escrow_lifecycle() {
+        let mut contract = new_contract();
+        trust_token_contract(&mut contract, accounts(1), accounts(4));
+        let preimage = b"secret".to_vec();
+        let hashlock = sha2::Sha256::digest(&preimage).to_vec();
+        let msg = near_sdk::serde_json::json!({
+            "receiver": accounts(2),
+            "hashlock": Base64VecU8(hashlock),
+            "timelock": 2_000_000,
+            "eth_address": "0x1234567890abcdef",
+        });
+
+        // nft_transfer_call of token "tiger-7" from accounts(1) on the NFT contract accounts(4)
+        let context = get_context(accounts(4));
+        testing_env!(context.build());
+        let returned = contract.nft_on_transfer(
+            accounts(1),
+            accounts(1),
+            "tiger-7".to_string(),
+            msg.to_string(),
+        );
+        assert!(matches!(returned, near_sdk::PromiseOrValue::Value(false)));
+        let contract_id = EscrowKind::Nft.escrow_id(0);
+        let escrow = contract.get_nft_escrow(contract_id.clone()).unwrap();
+        assert_eq!(escrow.nft_contract, accounts(4));
+        assert_eq!(escrow.token_id, "tiger-7");
+        assert_eq!(escrow.status, EscrowStatus::Active);
+        assert_eq!(contract.get_escrows_by_receiver(accounts(2), None, None).len(), 1);
+
+        let context = get_context(accounts(2));
+        testing_env!(context.build());
+        contract.complete_nft_swap(
+            contract_id.clone(),
+            Base64VecU8(preimage),
+            "0xabcdef".to_string(),
+        );
+        assert_eq!(last_event()["event"], "nft_swap_completed");
+        assert_eq!(last_event()["data"]["status"], "withdrawn");
+
+        // The transfer to the receiver failed, so it can be claimed again
+        let context = get_context(accounts(0));
+        testing_env!(
+            context.build(),
+            near_sdk::test_vm_config(),
+            near_sdk::RuntimeFeesConfig::test(),
+            Default::default(),
+            vec![near_sdk::PromiseResult::Failed],
+        );
+        assert!(!contract.on_nft_transfer_complete(contract_id.clone(), accounts(2)));
+        let escrow = contract.get_nft_escrow(contract_id.clone()).unwrap();
+        assert_eq!(escrow.status, EscrowStatus::Withdrawn);
+        assert!(escrow.transfer_failed);
+
+        let context = get_context(accounts(2));
+        testing_env!(context.build());
+        contract.claim_nft(contract_id.clone());
+        assert!(!contract.get_nft_escrow(contract_id).unwrap().transfer_failed);
+        let receipts = near_sdk::test_utils::get_created_receipts();
+        assert_eq!(receipts[0].receiver_id, accounts(4));
+    }
+
+    #[test]
+    #[should_panic(expected = "Token contract not trusted by the sender")]
+    fn test_nft_escrow_needs_trusted_contract() {
+        let mut contract = new_contract();
+        let msg = near_sdk::serde_json::json!({
+            "receiver": accounts(2),
+            "hashlock": Base64VecU8(vec![1u8; 32]),
+            "timelock": 2_000_000,
+            "eth_address": "0x1234567890abcdef",
+        });
+
+        testing_env!(get_context(accounts(4)).build());
+        contract.nft_on_transfer(
+            accounts(1),
+            accounts(1),
+            "tiger-7".to_string(),
+            msg.to_string(),
+        );
+    }
+
+    #[test]
+    fn test_keccak256_hashlock() {
+        let mut context = get_context(accounts(1));
+        testing_env!(context.attached_deposit(ATTACHED_DEPOSIT).build());
+
+        let mut contract = new_contract();
+        let preimage = b"test_secret";
+        let contract_id = contract.create_cross_chain_htlc(
+            accounts(2),
+            Base64VecU8(env::keccak256(preimage)),
+            2_000_000,
+            "0x1234567890abcdef".to_string(),
+            Some(HashAlgorithm::Keccak256),
+            None,
+            None,
+        );
+
+        let view = contract.get_cross_chain_contract(contract_id.clone()).unwrap();
+        assert_eq!(view.hash_algorithm, HashAlgorithm::Keccak256);
+        assert!(contract.check_preimage(contract_id.clone(), Base64VecU8(preimage.to_vec())));
+        assert!(!contract.check_preimage(contract_id.clone(), Base64VecU8(b"wrong".to_vec())));
+
+        let context = get_context(accounts(2));
+        testing_env!(context.build());
+        contract.complete_cross_chain_swap(
+            contract_id.clone(),
+            Base64VecU8(preimage.to_vec()),
+            "0xabcdef".to_string(),
+        );
+        assert_eq!(
+            contract.get_cross_chain_contract(contract_id).unwrap().status,
+            EscrowStatus::Withdrawn
+        );
+    }
+
+    #[test]
+    fn test_cross_chain_timelock_stages() {
+        let mut context = get_context(accounts(1));
+        testing_env!(context
+            .attached_deposit(ATTACHED_DEPOSIT)
+            .block_timestamp(ms_to_ns(1_000_000))
+            .build());
+
+        let mut contract = new_contract();
+        let timelocks = Timelocks {
+            withdrawal: 2_000_000,
+            public_withdrawal: 3_000_000,
+            cancellation: 4_000_000,
+        };
+        let contract_id = contract.create_cross_chain_htlc(
+            accounts(2),
+            Base64VecU8(vec![1u8; 32]),
+            3_000_000,
+            "0x1234567890abcdef".to_string(),
+            None,
+            Some(timelocks),
+            None,
+        );
+        assert_eq!(
+            contract.get_cross_chain_contract(contract_id.clone()).unwrap().timelocks,
+            timelocks
+        );
+
+        for (time, stage) in [
+            (2_000_000, TimelockStage::ExclusiveWithdrawal),
+            (2_500_000, TimelockStage::PublicWithdrawal),
+            (3_500_000, TimelockStage::Cancellation),
+            (4_000_001, TimelockStage::PublicCancellation),
+        ] {
+            let mut context = get_context(accounts(1));
+            testing_env!(context.block_timestamp(ms_to_ns(time)).build());
+            assert_eq!(contract.get_current_stage(contract_id.clone()), Some(stage));
+        }
+
+        // The sender may cancel once the withdrawal stages are over
+        let mut context = get_context(accounts(1));
+        testing_env!(context.block_timestamp(ms_to_ns(3_500_000)).build());
+        contract.refund_cross_chain(contract_id.clone());
+        assert_eq!(
+            contract.get_cross_chain_contract(contract_id).unwrap().status,
+            EscrowStatus::Refunded
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "Timelock not expired")]
+    fn test_cross_chain_refund_in_public_withdrawal_stage() {
+        let mut context = get_context(accounts(1));
+        testing_env!(context
+            .attached_deposit(ATTACHED_DEPOSIT)
+            .block_timestamp(ms_to_ns(1_000_000))
+            .build());
+
+        let mut contract = new_contract();
+        let contract_id = contract.create_cross_chain_htlc(
+            accounts(2),
+            Base64VecU8(vec![1u8; 32]),
+            3_000_000,
+            "0x1234567890abcdef".to_string(),
+            None,
+            Some(Timelocks {
+                withdrawal: 2_000_000,
+                public_withdrawal: 3_000_000,
+                cancellation: 4_000_000,
+            }),
+            None,
+        );
+
+        let mut context = get_context(accounts(1));
+        testing_env!(context.block_timestamp(ms_to_ns(2_500_000)).build());
+        contract.refund_cross_chain(contract_id);
+    }
+
+    #[test]
+    fn test_public_withdraw_pays_bounty() {
+        let mut context = get_context(accounts(1));
+        testing_env!(context
+            .attached_deposit(ATTACHED_DEPOSIT)
+            .block_timestamp(ms_to_ns(1_000_000))
+            .build());
+
+        let mut contract = new_contract();
+        let preimage = b"test_secret".to_vec();
+        let safety_deposit = NearToken::from_millinear(100).as_yoctonear();
+        let contract_id = contract.create_cross_chain_htlc(
+            accounts(2),
+            Base64VecU8(sha2::Sha256::digest(&preimage).to_vec()),
+            3_000_000,
+            "0x1234567890abcdef".to_string(),
+            None,
+            Some(Timelocks {
+                withdrawal: 2_000_000,
+                public_withdrawal: 3_000_000,
+                cancellation: 4_000_000,
+            }),
+            Some(U128(safety_deposit)),
+        );
+        let view = contract.get_cross_chain_contract(contract_id.clone()).unwrap();
+        assert_eq!(view.amount.0, ATTACHED_DEPOSIT.as_yoctonear() - safety_deposit);
+        assert_eq!(view.safety_deposit, U128(safety_deposit));
+
+        // A third party finishes the swap after the exclusive window
+        let mut context = get_context(accounts(3));
+        testing_env!(context.block_timestamp(ms_to_ns(2_500_000)).build());
+        contract.public_withdraw(contract_id.clone(), Base64VecU8(preimage));
+
+        let event = last_event();
+        assert_eq!(event["event"], "cross_chain_public_withdrawn");
+        assert_eq!(event["data"]["caller"], accounts(3).to_string());
+        assert_eq!(event["data"]["bounty"], safety_deposit.to_string());
+        assert_eq!(
+            contract.get_cross_chain_contract(contract_id).unwrap().status,
+            EscrowStatus::Withdrawn
+        );
+        let receipts = near_sdk::test_utils::get_created_receipts();
+        assert!(receipts.iter().any(|receipt| receipt.receiver_id == accounts(3)));
+        assert!(get_logs().iter().any(|log| log.contains("safety_deposit_slashed")));
+    }
+
+    #[test]
+    fn test_token_escrow_safety_deposit_locked_separately() {
+        testing_env!(get_context(accounts(0)).build());
+        let mut contract = new_contract();
+        contract.authorize_resolver(accounts(1));
+        trust_token_contract(&mut contract, accounts(1), accounts(4));
+        let storage_before = contract.storage_balance_of(accounts(1)).unwrap().total;
+        let msg = near_sdk::serde_json::json!({
+            "kind": "cross_chain",
+            "receiver": accounts(2),
+            "hashlock": Base64VecU8(vec![1u8; 32]),
+            "timelock": 2_000_000,
+            "eth_address": "0x1234567890abcdef",
+        });
+
+        // The transfer callback takes no NEAR from the resolver
+        testing_env!(get_context(accounts(4))
+            .block_timestamp(ms_to_ns(1_000_000))
+            .build());
+        contract.ft_on_transfer(accounts(1), U128(500), msg.to_string());
+        let contract_id = last_event()["data"]["id"].as_str().unwrap().to_string();
+        let view = contract.get_cross_chain_contract(contract_id.clone()).unwrap();
+        assert_eq!(view.safety_deposit, U128(0));
+        assert!(view.safety_deposit_pending);
+        assert_eq!(
+            contract.storage_balance_of(accounts(1)).unwrap().total,
+            storage_before
+        );
+
+        let safety_deposit = contract.get_min_safety_deposit();
+        testing_env!(get_context(accounts(1))
+            .attached_deposit(NearToken::from_yoctonear(safety_deposit.0))
+            .block_timestamp(ms_to_ns(1_000_000))
+            .build());
+        contract.lock_safety_deposit(contract_id.clone());
+        assert_eq!(last_event()["event"], "safety_deposit_locked");
+        let view = contract.get_cross_chain_contract(contract_id).unwrap();
+        assert_eq!(view.safety_deposit, safety_deposit);
+        assert!(!view.safety_deposit_pending);
+    }
+
+    #[test]
+    #[should_panic(expected = "Safety deposit not locked")]
+    fn test_resolver_token_escrow_needs_safety_deposit_to_settle() {
+        testing_env!(get_context(accounts(0)).build());
+        let mut contract = new_contract();
+        contract.authorize_resolver(accounts(1));
+        trust_token_contract(&mut contract, accounts(1), accounts(4));
+        let preimage = b"test_secret".to_vec();
+        let msg = near_sdk::serde_json::json!({
+            "kind": "cross_chain",
+            "receiver": accounts(2),
+            "hashlock": Base64VecU8(sha2::Sha256::digest(&preimage).to_vec()),
+            "timelock": 2_000_000,
+            "eth_address": "0x1234567890abcdef",
+        });
+        testing_env!(get_context(accounts(4))
+            .block_timestamp(ms_to_ns(1_000_000))
+            .build());
+        contract.ft_on_transfer(accounts(1), U128(500), msg.to_string());
+        let contract_id = last_event()["data"]["id"].as_str().unwrap().to_string();
+
+        testing_env!(get_context(accounts(2))
+            .block_timestamp(ms_to_ns(1_500_000))
+            .build());
+        contract.complete_cross_chain_swap(
+            contract_id,
+            Base64VecU8(preimage),
+            "0xabc".to_string(),
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "Only sender can lock the safety deposit")]
+    fn test_lock_safety_deposit_for_another_sender() {
+        let mut contract = new_contract();
+        trust_token_contract(&mut contract, accounts(1), accounts(4));
+        let msg = near_sdk::serde_json::json!({
+            "kind": "cross_chain",
+            "receiver": accounts(2),
+            "hashlock": Base64VecU8(vec![1u8; 32]),
+            "timelock": 2_000_000,
+            "eth_address": "0x1234567890abcdef",
+        });
+        testing_env!(get_context(accounts(4))
+            .block_timestamp(ms_to_ns(1_000_000))
+            .build());
+        contract.ft_on_transfer(accounts(1), U128(500), msg.to_string());
+        let contract_id = last_event()["data"]["id"].as_str().unwrap().to_string();
+
+        testing_env!(get_context(accounts(3))
+            .attached_deposit(ATTACHED_DEPOSIT)
+            .block_timestamp(ms_to_ns(1_000_000))
+            .build());
+        contract.lock_safety_deposit(contract_id);
+    }
+
+    #[test]
+    #[should_panic(expected = "Not in the public cancellation stage")]
+    fn test_public_cancel_before_public_stage() {
+        let mut context = get_context(accounts(1));
+        testing_env!(context
+            .attached_deposit(ATTACHED_DEPOSIT)
+            .block_timestamp(ms_to_ns(1_000_000))
+            .build());
+
+        let mut contract = new_contract();
+        let contract_id = contract.create_cross_chain_htlc(
+            accounts(2),
+            Base64VecU8(vec![1u8; 32]),
+            3_000_000,
+            "0x1234567890abcdef".to_string(),
+            None,
+            Some(Timelocks {
+                withdrawal: 2_000_000,
+                public_withdrawal: 3_000_000,
+                cancellation: 4_000_000,
+            }),
+            Some(U128(1)),
+        );
+
+        let mut context = get_context(accounts(3));
+        testing_env!(context.block_timestamp(ms_to_ns(3_500_000)).build());
+        contract.public_cancel(contract_id);
+    }
+
+    #[test]
+    fn test_single_timelock_has_no_public_cancellation() {
+        testing_env!(get_context(accounts(1))
+            .attached_deposit(NearToken::from_near(2))
+            .block_timestamp(ms_to_ns(1_000_000))
+            .build());
+        let mut contract = new_contract();
+        let contract_id = contract.create_cross_chain_htlc(
+            accounts(2),
+            Base64VecU8(vec![1u8; 32]),
+            2_000_000,
+            "0x1234567890abcdef".to_string(),
+            None,
+            None,
+            Some(U128(ATTACHED_DEPOSIT.as_yoctonear())),
+        );
+
+        // Long after the timelock only the sender may cancel
+        testing_env!(get_context(accounts(1))
+            .block_timestamp(ms_to_ns(1_000_000_000))
+            .build());
+        assert_eq!(
+            contract.get_current_stage(contract_id.clone()),
+            Some(TimelockStage::Cancellation)
+        );
+        contract.refund_cross_chain(contract_id.clone());
+        assert_eq!(last_event()["event"], "cross_chain_htlc_refunded");
+        assert!(get_logs()
+            .iter()
+            .any(|log| log.contains("safety_deposit_returned")));
+    }
+
+    #[test]
+    #[should_panic(expected = "Not in the public cancellation stage")]
+    fn test_public_cancel_with_single_timelock() {
+        testing_env!(get_context(accounts(1))
+            .attached_deposit(NearToken::from_near(2))
+            .block_timestamp(ms_to_ns(1_000_000))
+            .build());
+        let mut contract = new_contract();
+        let contract_id = contract.create_cross_chain_htlc(
+            accounts(2),
+            Base64VecU8(vec![1u8; 32]),
+            2_000_000,
+            "0x1234567890abcdef".to_string(),
+            None,
+            None,
+            Some(U128(ATTACHED_DEPOSIT.as_yoctonear())),
+        );
+
+        testing_env!(get_context(accounts(3))
+            .block_timestamp(ms_to_ns(1_000_000_000))
+            .build());
+        contract.public_cancel(contract_id);
+    }
+
+    #[test]
+    #[should_panic(expected = "Resolvers must attach the minimum safety deposit")]
+    fn test_resolver_below_min_safety_deposit() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.block_timestamp(ms_to_ns(1_000_000)).build());
+        let mut contract = new_contract();
+        contract.authorize_resolver(accounts(1));
+
+        let mut context = get_context(accounts(1));
+        testing_env!(context
+            .attached_deposit(ATTACHED_DEPOSIT)
+            .block_timestamp(ms_to_ns(1_000_000))
+            .build());
+        contract.create_cross_chain_htlc(
+            accounts(2),
+            Base64VecU8(vec![1u8; 32]),
+            2_000_000,
+            "0x1234567890abcdef".to_string(),
+            None,
+            None,
+            Some(U128(1)),
+        );
+    }
+
+    #[test]
+    fn test_safety_deposit_returned_on_timely_withdrawal() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.block_timestamp(ms_to_ns(1_000_000)).build());
+        let mut contract = new_contract();
+        contract.authorize_resolver(accounts(1));
+        let safety_deposit = contract.get_min_safety_deposit();
+
+        let mut context = get_context(accounts(1));
+        testing_env!(context
+            .attached_deposit(ATTACHED_DEPOSIT)
+            .block_timestamp(ms_to_ns(1_000_000))
+            .build());
+        let preimage = b"test_secret".to_vec();
+        let contract_id = contract.create_cross_chain_htlc(
+            accounts(2),
+            Base64VecU8(sha2::Sha256::digest(&preimage).to_vec()),
+            2_000_000,
+            "0x1234567890abcdef".to_string(),
+            None,
+            None,
+            Some(safety_deposit),
+        );
+
+        let mut context = get_context(accounts(2));
+        testing_env!(context.block_timestamp(ms_to_ns(1_500_000)).build());
+        contract.complete_cross_chain_swap(
+            contract_id.clone(),
+            Base64VecU8(preimage),
+            "0xabc".to_string(),
+        );
+
+        let logs = get_logs();
+        let returned = logs
+            .iter()
+            .find(|log| log.contains("safety_deposit_returned"))
+            .expect("No safety deposit event");
+        assert!(returned.contains(&contract_id));
+        assert!(returned.contains(&safety_deposit.0.to_string()));
+        assert!(!logs.iter().any(|log| log.contains("safety_deposit_slashed")));
+    }
+
+    #[test]
+    #[should_panic(expected = "Only authorized resolvers")]
+    fn test_revoked_resolver_cannot_create_destination_escrow() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.block_timestamp(ms_to_ns(1_000_000)).build());
+        let mut contract = new_contract();
+        contract.authorize_resolver(accounts(1));
+        contract.authorize_resolver(accounts(3));
+        assert_eq!(contract.get_resolvers(None, None), vec![accounts(1), accounts(3)]);
+        assert_eq!(contract.get_resolvers(Some(1), Some(1)), vec![accounts(3)]);
+
+        contract.revoke_resolver(accounts(1));
+        assert_eq!(last_event()["event"], "resolver_revoked");
+        assert!(!contract.is_authorized_resolver(accounts(1)));
+        assert_eq!(contract.get_resolvers(None, None), vec![accounts(3)]);
+
+        let mut context = get_context(accounts(1));
+        testing_env!(context
+            .attached_deposit(ATTACHED_DEPOSIT)
+            .block_timestamp(ms_to_ns(1_000_000))
+            .build());
+        contract.create_destination_htlc(
+            accounts(2),
+            Base64VecU8(vec![1u8; 32]),
+            2_000_000,
+            "0x1234567890abcdef".to_string(),
+            None,
+            None,
+            None,
+        );
+    }
+
+    #[test]
+    fn test_permissionless_destination_escrow() {
+        testing_env!(get_context(accounts(0)).build());
+        let mut contract = new_contract();
+        assert!(!contract.is_permissionless());
+        contract.set_permissionless(true);
+        assert_eq!(last_event()["event"], "permissionless_mode_set");
+
+        testing_env!(get_context(accounts(1))
+            .attached_deposit(ATTACHED_DEPOSIT)
+            .block_timestamp(ms_to_ns(1_000_000))
+            .build());
+        let contract_id = contract.create_destination_htlc(
+            accounts(2),
+            Base64VecU8(vec![1u8; 32]),
+            2_000_000,
+            "0x1234567890abcdef".to_string(),
+            None,
+            None,
+            Some(contract.get_min_safety_deposit()),
+        );
+        assert_eq!(
+            contract.get_cross_chain_contract(contract_id).unwrap().receiver,
+            accounts(2)
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "Resolvers must attach the minimum safety deposit")]
+    fn test_permissionless_destination_escrow_needs_safety_deposit() {
+        testing_env!(get_context(accounts(0)).build());
+        let mut contract = new_contract();
+        contract.set_permissionless(true);
+
+        // Not an authorized resolver, but still acting as one
+        testing_env!(get_context(accounts(1))
+            .attached_deposit(ATTACHED_DEPOSIT)
+            .block_timestamp(ms_to_ns(1_000_000))
+            .build());
+        contract.create_destination_htlc(
+            accounts(2),
+            Base64VecU8(vec![1u8; 32]),
+            2_000_000,
+            "0x1234567890abcdef".to_string(),
+            None,
+            None,
+            None,
+        );
+    }
+
+    #[test]
+    fn test_revoked_resolver_completes_claimed_fill() {
+        let mut context = get_context(accounts(1));
+        testing_env!(context
+            .attached_deposit(ATTACHED_DEPOSIT)
+            .block_timestamp(ms_to_ns(1_000_000))
+            .build());
+        let mut contract = new_contract();
+        with_resolver(&mut contract);
+        let (secrets, fills) = swap_secrets(1);
+        let swap_id = contract.create_partial_fill_swap(
+            U128(ATTACHED_DEPOSIT.as_yoctonear()),
+            "0x1234567890abcdef".to_string(),
+            2_000_000,
+            None,
+            secrets,
+        );
+        testing_env!(get_context(accounts(2))
+            .block_timestamp(ms_to_ns(1_000_000))
+            .build());
+        let fill_id = contract.create_partial_fill(
+            swap_id,
+            fills[1].0.clone(),
+            U128(ATTACHED_DEPOSIT.as_yoctonear()),
+            fills[1].1.clone(),
+        );
+
+        // Revoking the resolver only stops new claims
+        testing_env!(get_context(accounts(0))
+            .block_timestamp(ms_to_ns(1_000_000))
+            .build());
+        contract.revoke_resolver(accounts(2));
+
+        testing_env!(get_context(accounts(2))
+            .block_timestamp(ms_to_ns(1_500_000))
+            .build());
+        contract.complete_partial_fill(
+            fill_id.clone(),
+            Base64VecU8(vec![1u8; 32]),
+            "0xabc".to_string(),
+        );
+        assert_eq!(
+            contract.get_partial_fill(fill_id).unwrap().status,
+            EscrowStatus::Withdrawn
+        );
+    }
+
+    #[test]
+    fn test_roles_granted_and_revoked() {
+        testing_env!(get_context(accounts(0)).build());
+        let mut contract = new_contract();
+        contract.grant_role(Role::Pauser, accounts(1));
+        assert_eq!(last_event()["event"], "role_granted");
+        assert_eq!(last_event()["data"]["role"], "pauser");
+        assert_eq!(contract.get_role_members(Role::Pauser, None, None), vec![accounts(1)]);
+        assert!(!contract.has_role(Role::ResolverManager, accounts(1)));
+
+        testing_env!(get_context(accounts(1)).build());
+        contract.emergency_pause();
+        assert!(contract.is_paused());
+
+        testing_env!(get_context(accounts(0)).build());
+        contract.revoke_role(Role::Pauser, accounts(1));
+        assert_eq!(last_event()["event"], "role_revoked");
+        assert!(!contract.has_role(Role::Pauser, accounts(1)));
+    }
+
+    #[test]
+    #[should_panic(expected = "Missing role ResolverManager")]
+    fn test_pauser_cannot_manage_resolvers() {
+        testing_env!(get_context(accounts(0)).build());
+        let mut contract = new_contract();
+        contract.grant_role(Role::Pauser, accounts(1));
+
+        testing_env!(get_context(accounts(1)).build());
+        contract.authorize_resolver(accounts(2));
+    }
+
+    #[test]
+    fn test_two_step_ownership_transfer() {
+        testing_env!(get_context(accounts(0)).build());
+        let mut contract = new_contract();
+        contract.propose_owner(accounts(1));
+        assert_eq!(contract.get_pending_owner(), Some(accounts(1)));
+        assert_eq!(contract.get_owner(), accounts(0));
+
+        testing_env!(get_context(accounts(1)).build());
+        contract.accept_ownership();
+        assert_eq!(last_event()["event"], "ownership_transferred");
+        assert_eq!(contract.get_owner(), accounts(1));
+        assert_eq!(contract.get_pending_owner(), None);
+        assert!(contract.has_role(Role::Upgrader, accounts(1)));
+        assert!(!contract.has_role(Role::Upgrader, accounts(0)));
+    }
+
+    #[test]
+    #[should_panic(expected = "Only the proposed owner can accept")]
+    fn test_accept_ownership_without_proposal() {
+        testing_env!(get_context(accounts(0)).build());
+        let mut contract = new_contract();
+        contract.propose_owner(accounts(1));
+
+        testing_env!(get_context(accounts(2)).build());
+        contract.accept_ownership();
+    }
+
+    #[test]
+    fn test_fee_taken_on_withdraw() {
+        let mut context = get_context(accounts(1));
+        testing_env!(context
+            .attached_deposit(ATTACHED_DEPOSIT)
+            .block_timestamp(ms_to_ns(1_000_000))
+            .build());
+        let mut contract = new_contract();
+        let preimage = b"test_secret".to_vec();
+        let contract_id = contract.create_htlc(
+            accounts(2),
+            Base64VecU8(sha2::Sha256::digest(&preimage).to_vec()),
+            2_000_000,
+            "0x1234567890abcdef".to_string(),
+            None,
+        );
+
+        assert_eq!(contract.get_contract(contract_id.clone()).unwrap().fee_bps, 30);
+
+        // A later fee change doesn't apply to the open escrow
+        testing_env!(get_context(accounts(0)).build());
+        contract.set_fee_bps(1_000);
+
+        let mut context = get_context(accounts(2));
+        testing_env!(context.block_timestamp(ms_to_ns(1_500_000)).build());
+        contract.withdraw(contract_id, Base64VecU8(preimage));
+
+        let fee = ATTACHED_DEPOSIT.as_yoctonear() * 30 / 10_000;
+        assert_eq!(last_event()["data"]["fee"], fee.to_string());
+        assert_eq!(contract.get_fee_balance(None), U128(fee));
+        assert_eq!(contract.get_fee_balances(None, None), vec![(None, U128(fee))]);
+
+        testing_env!(get_context(accounts(0)).build());
+        assert_eq!(contract.withdraw_fees(None, None, Some(accounts(3))), U128(fee));
+        assert_eq!(last_event()["event"], "fees_withdrawn");
+        assert_eq!(contract.get_fee_balance(None), U128(0));
+    }
+
+    #[test]
+    #[should_panic(expected = "Fee too high")]
+    fn test_set_fee_above_max() {
+        testing_env!(get_context(accounts(0)).build());
+        let mut contract = new_contract();
+        contract.set_fee_bps(fees::MAX_FEE_BPS + 1);
+    }
+
+    #[test]
+    fn test_dutch_auction_prices_fills() {
+        testing_env!(get_context(accounts(1))
+            .attached_deposit(NearToken::from_near(2))
+            .block_timestamp(ms_to_ns(1_000_000))
+            .build());
+        let mut contract = new_contract();
+        with_resolver(&mut contract);
+        let (secrets, fills) = swap_secrets(2);
+        let swap_id = contract.create_partial_fill_swap(
+            U128(2 * ATTACHED_DEPOSIT.as_yoctonear()),
+            "0x1234567890abcdef".to_string(),
+            2_000_000,
+            Some(DutchAuction {
+                start_rate: U128(2_000),
+                end_rate: U128(1_000),
+                start_time: 1_000_000,
+                duration: 100_000,
+                curve: vec![AuctionPoint {
+                    offset: 20_000,
+                    rate: U128(1_200),
+                }],
+            }),
+            secrets,
+        );
+        assert_eq!(contract.get_current_rate(swap_id.clone()), Some(U128(2_000)));
+
+        // Halfway down the first, steeper segment of the curve
+        let mut context = get_context(accounts(2));
+        testing_env!(context.block_timestamp(ms_to_ns(1_010_000)).build());
+        assert_eq!(contract.get_current_rate(swap_id.clone()), Some(U128(1_600)));
+        let fill_id = contract.create_partial_fill(
+            swap_id.clone(),
+            fills[0].0.clone(),
+            U128(ATTACHED_DEPOSIT.as_yoctonear()),
+            fills[0].1.clone(),
+        );
+        let expected = ATTACHED_DEPOSIT.as_yoctonear() * 1_600 / auction::RATE_SCALE;
+        assert_eq!(
+            contract.get_partial_fill(fill_id).unwrap().min_destination_amount,
+            Some(U128(expected))
+        );
+        assert_eq!(
+            last_event()["data"]["min_destination_amount"],
+            expected.to_string()
+        );
+
+        // Rests at the end rate once the auction is over
+        testing_env!(context.block_timestamp(ms_to_ns(1_500_000)).build());
+        assert_eq!(contract.get_current_rate(swap_id.clone()), Some(U128(1_000)));
+        assert_eq!(
+            contract.get_fill_price(swap_id, U128(auction::RATE_SCALE)),
+            Some(U128(1_000))
+        );
+    }
+
+    #[test]
+    fn test_dutch_auction_six_decimal_rates() {
+        testing_env!(get_context(accounts(1))
+            .attached_deposit(ATTACHED_DEPOSIT)
+            .block_timestamp(ms_to_ns(1_000_000))
+            .build());
+        let mut contract = new_contract();
+        // 3 USDC down to 2.9 USDC per NEAR
+        let swap_id = contract.create_partial_fill_swap(
+            U128(ATTACHED_DEPOSIT.as_yoctonear()),
+            "0x1234567890abcdef".to_string(),
+            2_000_000,
+            Some(DutchAuction {
+                start_rate: U128(3_000_000),
+                end_rate: U128(2_900_000),
+                start_time: 1_000_000,
+                duration: 100_000,
+                curve: vec![],
+            }),
+            swap_secrets(1).0,
+        );
+
+        // 1% into the auction the rate moved by 1% of its range
+        testing_env!(get_context(accounts(2))
+            .block_timestamp(ms_to_ns(1_001_000))
+            .build());
+        assert_eq!(contract.get_current_rate(swap_id.clone()), Some(U128(2_999_000)));
+        assert_eq!(
+            contract.get_fill_price(swap_id, U128(ATTACHED_DEPOSIT.as_yoctonear() / 2)),
+            Some(U128(1_499_500))
+        );
+    }
+
+    #[test]
+    fn test_dutch_auction_large_eighteen_decimal_fills() {
+        // 3 tokens of 18 decimals per NEAR for a million NEAR
+        let rate = U128(3 * 10u128.pow(18));
+        let amount = U128(10u128.pow(30));
+        assert_eq!(
+            DutchAuction::destination_amount(amount, rate),
+            U128(3 * 10u128.pow(24))
+        );
+        // Products past 128 bits are fine as long as the result fits
+        assert_eq!(
+            DutchAuction::destination_amount(
+                U128(u128::MAX / 3),
+                U128(3 * auction::RATE_SCALE)
+            ),
+            U128(u128::MAX)
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "Destination amount overflow")]
+    fn test_dutch_auction_destination_amount_overflow() {
+        DutchAuction::destination_amount(U128(u128::MAX), U128(2 * auction::RATE_SCALE));
+    }
+
+    #[test]
+    #[should_panic(expected = "Auction curve offsets must increase within the duration")]
+    fn test_dutch_auction_curve_outside_duration() {
+        testing_env!(get_context(accounts(1))
+            .attached_deposit(ATTACHED_DEPOSIT)
+            .block_timestamp(ms_to_ns(1_000_000))
+            .build());
+        let mut contract = new_contract();
+        with_resolver(&mut contract);
+        contract.create_partial_fill_swap(
+            U128(ATTACHED_DEPOSIT.as_yoctonear()),
+            "0x1234567890abcdef".to_string(),
+            2_000_000,
+            Some(DutchAuction {
+                start_rate: U128(2_000),
+                end_rate: U128(1_000),
+                start_time: 1_000_000,
+                duration: 100_000,
+                curve: vec![AuctionPoint {
+                    offset: 100_000,
+                    rate: U128(1_200),
+                }],
+            }),
+            swap_secrets(1).0,
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "Auction rates must not increase")]
+    fn test_dutch_auction_rising_curve() {
+        testing_env!(get_context(accounts(1))
+            .attached_deposit(ATTACHED_DEPOSIT)
+            .block_timestamp(ms_to_ns(1_000_000))
+            .build());
+        let mut contract = new_contract();
+        with_resolver(&mut contract);
+        contract.create_partial_fill_swap(
+            U128(ATTACHED_DEPOSIT.as_yoctonear()),
+            "0x1234567890abcdef".to_string(),
+            2_000_000,
+            Some(DutchAuction {
+                start_rate: U128(2_000),
+                end_rate: U128(1_000),
+                start_time: 1_000_000,
+                duration: 100_000,
+                curve: vec![AuctionPoint {
+                    offset: 50_000,
+                    rate: U128(2_500),
+                }],
+            }),
+            swap_secrets(1).0,
+        );
+    }
+
+    // Root of a Merkle tree over `secret_hashes` and the proof of each leaf,
+    // with an odd node out carried up to the next level as is
+    fn merkle_tree(
+        hash_algorithm: &HashAlgorithm,
+        secret_hashes: &[Vec<u8>],
+    ) -> (Vec<u8>, Vec<Vec<Base64VecU8>>) {
+        let mut level = secret_hashes
+            .iter()
+            .enumerate()
+            .map(|(i, secret_hash)| {
+                let mut leaf = vec![0u8; 28];
+                leaf.extend_from_slice(&(i as u32).to_be_bytes());
+                leaf.extend_from_slice(secret_hash);
+                hash_algorithm.hash(&leaf)
+            })
+            .collect::<Vec<_>>();
+        let mut positions = (0..level.len()).collect::<Vec<_>>();
+        let mut proofs = vec![vec![]; level.len()];
+        while level.len() > 1 {
+            for (proof, position) in proofs.iter_mut().zip(positions.iter_mut()) {
+                if let Some(sibling) = level.get(*position ^ 1) {
+                    proof.push(Base64VecU8(sibling.clone()));
+                }
+                *position /= 2;
+            }
+            level = level
+                .chunks(2)
+                .map(|pair| match pair {
+                    [a, b] if a <= b => hash_algorithm.hash(&[a.as_slice(), b].concat()),
+                    [a, b] => hash_algorithm.hash(&[b.as_slice(), a].concat()),
+                    _ => pair[0].clone(),
+                })
+                .collect();
+        }
+        (level.remove(0), proofs)
+    }
+
+    // Secret hashes, their Merkle proofs and the root of a swap split into
+    // two parts
+    fn merkle_secrets() -> (Vec<Vec<u8>>, Vec<Vec<Base64VecU8>>, Vec<u8>) {
+        let keccak = HashAlgorithm::Keccak256;
+        let secret_hashes = (0u8..3)
+            .map(|i| keccak.hash(&[i; 32]))
+            .collect::<Vec<_>>();
+        let (root, proofs) = merkle_tree(&keccak, &secret_hashes);
+        (secret_hashes, proofs, root)
+    }
+
+    // SHA-256 secrets splitting a swap into `parts`, with the hashlock and
+    // fill secret of each index; the preimage of index `i` is `[i; 32]`
+    fn swap_secrets(parts: u32) -> (MerkleSecrets, Vec<(Base64VecU8, FillSecret)>) {
+        let sha256 = HashAlgorithm::Sha256;
+        let secret_hashes = (0..=parts)
+            .map(|i| sha256.hash(&[i as u8; 32]))
+            .collect::<Vec<_>>();
+        let (root, proofs) = merkle_tree(&sha256, &secret_hashes);
+        let fills = secret_hashes
+            .into_iter()
+            .zip(proofs)
+            .enumerate()
+            .map(|(index, (secret_hash, proof))| {
+                (
+                    Base64VecU8(secret_hash),
+                    FillSecret {
+                        index: index as u32,
+                        proof,
+                    },
+                )
+            })
+            .collect();
+        let secrets = MerkleSecrets {
+            root: Base64VecU8(root),
+            parts,
+            hash_algorithm: sha256,
+        };
+        (secrets, fills)
+    }
+
+    #[test]
+    fn test_merkle_secret_fills() {
+        let (secret_hashes, proofs, root) = merkle_secrets();
+        testing_env!(get_context(accounts(1))
+            .attached_deposit(NearToken::from_near(2))
+            .block_timestamp(ms_to_ns(1_000_000))
+            .build());
+        let mut contract = new_contract();
+        with_resolver(&mut contract);
+        let swap_id = contract.create_partial_fill_swap(
+            U128(2 * ATTACHED_DEPOSIT.as_yoctonear()),
+            "0x1234567890abcdef".to_string(),
+            2_000_000,
+            None,
+            MerkleSecrets {
+                root: Base64VecU8(root),
+                parts: 2,
+                hash_algorithm: HashAlgorithm::Keccak256,
+            },
+        );
+
+        testing_env!(get_context(accounts(2))
+            .block_timestamp(ms_to_ns(1_000_000))
+            .build());
+        let first = contract.create_partial_fill(
+            swap_id.clone(),
+            Base64VecU8(secret_hashes[0].clone()),
+            U128(ATTACHED_DEPOSIT.as_yoctonear()),
+            FillSecret {
+                index: 0,
+                proof: proofs[0].clone(),
+            },
+        );
+        let fill = contract.get_partial_fill(first).unwrap();
+        assert_eq!(fill.secret_index, Some(0));
+        // Fills take the hash algorithm of the swap's secrets
+        assert_eq!(fill.hash_algorithm, HashAlgorithm::Keccak256);
+
+        // The fill completing the swap takes the last secret
+        contract.create_partial_fill(
+            swap_id.clone(),
+            Base64VecU8(secret_hashes[2].clone()),
+            U128(ATTACHED_DEPOSIT.as_yoctonear()),
+            FillSecret {
+                index: 2,
+                proof: proofs[2].clone(),
+            },
+        );
+        let swap = contract.get_partial_fill_swap(swap_id).unwrap();
+        assert_eq!(swap.last_secret_index, Some(2));
+        assert_eq!(swap.status, SwapStatus::FullyAllocated);
+    }
+
+    #[test]
+    #[should_panic(expected = "Secret index does not match the filled amount")]
+    fn test_merkle_secret_index_checked_against_fill() {
+        let (secret_hashes, proofs, root) = merkle_secrets();
+        testing_env!(get_context(accounts(1))
+            .attached_deposit(NearToken::from_near(2))
+            .block_timestamp(ms_to_ns(1_000_000))
+            .build());
+        let mut contract = new_contract();
+        with_resolver(&mut contract);
+        let swap_id = contract.create_partial_fill_swap(
+            U128(2 * ATTACHED_DEPOSIT.as_yoctonear()),
+            "0x1234567890abcdef".to_string(),
+            2_000_000,
+            None,
+            MerkleSecrets {
+                root: Base64VecU8(root),
+                parts: 2,
+                hash_algorithm: HashAlgorithm::Keccak256,
+            },
+        );
+
+        // Half of the swap only reaches the first part
+        testing_env!(get_context(accounts(2))
+            .block_timestamp(ms_to_ns(1_000_000))
+            .build());
+        contract.create_partial_fill(
+            swap_id,
+            Base64VecU8(secret_hashes[1].clone()),
+            U128(ATTACHED_DEPOSIT.as_yoctonear()),
+            FillSecret {
+                index: 1,
+                proof: proofs[1].clone(),
+            },
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "Invalid Merkle proof")]
+    fn test_fill_hashlock_must_be_committed_by_maker() {
+        testing_env!(get_context(accounts(1))
+            .attached_deposit(ATTACHED_DEPOSIT)
+            .block_timestamp(ms_to_ns(1_000_000))
+            .build());
+        let mut contract = new_contract();
+        with_resolver(&mut contract);
+        let (secrets, fills) = swap_secrets(1);
+        let swap_id = contract.create_partial_fill_swap(
+            U128(ATTACHED_DEPOSIT.as_yoctonear()),
+            "0x1234567890abcdef".to_string(),
+            2_000_000,
+            None,
+            secrets,
+        );
+
+        // A resolver can't swap in a hashlock of its own choosing
+        testing_env!(get_context(accounts(2))
+            .block_timestamp(ms_to_ns(1_000_000))
+            .build());
+        contract.create_partial_fill(
+            swap_id,
+            Base64VecU8(sha2::Sha256::digest(b"resolver_secret").to_vec()),
+            U128(ATTACHED_DEPOSIT.as_yoctonear()),
+            fills[1].1.clone(),
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "Only authorized resolvers")]
+    fn test_only_resolvers_claim_fills() {
+        testing_env!(get_context(accounts(1))
+            .attached_deposit(ATTACHED_DEPOSIT)
+            .block_timestamp(ms_to_ns(1_000_000))
+            .build());
+        let mut contract = new_contract();
+        let (secrets, fills) = swap_secrets(1);
+        let swap_id = contract.create_partial_fill_swap(
+            U128(ATTACHED_DEPOSIT.as_yoctonear()),
+            "0x1234567890abcdef".to_string(),
+            2_000_000,
+            None,
+            secrets,
+        );
+
+        testing_env!(get_context(accounts(2))
+            .block_timestamp(ms_to_ns(1_000_000))
+            .build());
+        contract.create_partial_fill(
+            swap_id,
+            fills[1].0.clone(),
+            U128(ATTACHED_DEPOSIT.as_yoctonear()),
+            fills[1].1.clone(),
+        );
+    }
+
+    #[test]
+    fn test_cancel_partial_fill_swap() {
+        testing_env!(get_context(accounts(1))
+            .attached_deposit(NearToken::from_near(3))
+            .block_timestamp(ms_to_ns(1_000_000))
+            .build());
+        let mut contract = new_contract();
+        with_resolver(&mut contract);
+        let (secrets, fills) = swap_secrets(3);
+        let swap_id = contract.create_partial_fill_swap(
+            U128(3 * ATTACHED_DEPOSIT.as_yoctonear()),
+            "0x1234567890abcdef".to_string(),
+            2_000_000,
+            None,
+            secrets,
+        );
+
+        testing_env!(get_context(accounts(2))
+            .block_timestamp(ms_to_ns(1_000_000))
+            .build());
+        let fill_id = contract.create_partial_fill(
+            swap_id.clone(),
+            fills[0].0.clone(),
+            U128(ATTACHED_DEPOSIT.as_yoctonear()),
+            fills[0].1.clone(),
+        );
+
+        testing_env!(get_context(accounts(1))
+            .block_timestamp(ms_to_ns(1_100_000))
+            .build());
+        let refunded = contract.cancel_partial_fill_swap(swap_id.clone());
+        assert_eq!(refunded.0, 2 * ATTACHED_DEPOSIT.as_yoctonear());
+        assert_eq!(last_event()["event"], "partial_fill_swap_cancelled");
+        let swap = contract.get_partial_fill_swap(swap_id.clone()).unwrap();
+        assert_eq!(swap.status, SwapStatus::Cancelled);
+        assert_eq!(swap.remaining_amount.0, 0);
+        assert_eq!(
+            contract
+                .get_all_partial_fill_swaps(None, None, Some(SwapStatus::Cancelled))
+                .len(),
+            1
+        );
+        assert!(contract
+            .get_all_partial_fill_swaps(None, None, Some(SwapStatus::Open))
+            .is_empty());
+
+        // The existing fill still settles
+        testing_env!(get_context(accounts(2))
+            .block_timestamp(ms_to_ns(1_200_000))
+            .build());
+        contract.complete_partial_fill(fill_id.clone(), Base64VecU8(vec![0u8; 32]), "0xabc".to_string());
+        assert_eq!(last_event()["event"], "partial_fill_completed");
+        assert_eq!(last_event()["data"]["status"], "withdrawn");
+        assert_eq!(
+            contract.get_partial_fill(fill_id).unwrap().status,
+            EscrowStatus::Withdrawn
+        );
+        assert_eq!(
+            contract.get_partial_fill_swap(swap_id).unwrap().status,
+            SwapStatus::Cancelled
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "Swap already fully allocated")]
+    fn test_cancel_fully_allocated_swap() {
+        testing_env!(get_context(accounts(1))
+            .attached_deposit(ATTACHED_DEPOSIT)
+            .block_timestamp(ms_to_ns(1_000_000))
+            .build());
+        let mut contract = new_contract();
+        with_resolver(&mut contract);
+        let (secrets, fills) = swap_secrets(1);
+        let swap_id = contract.create_partial_fill_swap(
+            U128(ATTACHED_DEPOSIT.as_yoctonear()),
+            "0x1234567890abcdef".to_string(),
+            2_000_000,
+            None,
+            secrets,
+        );
+
+        testing_env!(get_context(accounts(2))
+            .block_timestamp(ms_to_ns(1_000_000))
+            .build());
+        contract.create_partial_fill(
+            swap_id.clone(),
+            fills[1].0.clone(),
+            U128(ATTACHED_DEPOSIT.as_yoctonear()),
+            fills[1].1.clone(),
+        );
+
+        testing_env!(get_context(accounts(1))
+            .block_timestamp(ms_to_ns(1_100_000))
+            .build());
+        contract.cancel_partial_fill_swap(swap_id);
+    }
+
+    #[test]
+    #[should_panic(expected = "Swap cancelled")]
+    fn test_no_fills_after_cancel() {
+        testing_env!(get_context(accounts(1))
+            .attached_deposit(ATTACHED_DEPOSIT)
+            .block_timestamp(ms_to_ns(1_000_000))
+            .build());
+        let mut contract = new_contract();
+        with_resolver(&mut contract);
+        let (secrets, fills) = swap_secrets(1);
+        let swap_id = contract.create_partial_fill_swap(
+            U128(ATTACHED_DEPOSIT.as_yoctonear()),
+            "0x1234567890abcdef".to_string(),
+            2_000_000,
+            None,
+            secrets,
+        );
+        contract.cancel_partial_fill_swap(swap_id.clone());
+
+        testing_env!(get_context(accounts(2))
+            .block_timestamp(ms_to_ns(1_000_000))
+            .build());
+        contract.create_partial_fill(
+            swap_id,
+            fills[0].0.clone(),
+            U128(1),
+            fills[0].1.clone(),
+        );
+    }
+}
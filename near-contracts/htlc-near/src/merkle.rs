@@ -0,0 +1,107 @@
+use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
+use near_sdk::json_types::Base64VecU8;
+use near_sdk::serde::{Deserialize, Serialize};
+use near_sdk::{env, NearSchema};
+
+use crate::{HashAlgorithm, PartialFillSwap};
+
+/// Most parts a swap's secrets may split it into.
+pub const MAX_SECRET_PARTS: u32 = 1_024;
+
+/// Fusion+ commitment of a partial fill swap to `parts + 1` secrets: the
+/// root of a Merkle tree whose leaves are `hash(index ++ secret_hash)`,
+/// with the index as 32 big-endian bytes and sorted pairs hashed together.
+/// Secret `i` unlocks the fill that brings the swap into its `i`-th part,
+/// secret `parts` the fill that completes it.
+#[derive(
+    BorshDeserialize, BorshSerialize, Serialize, Deserialize, NearSchema, Clone, PartialEq, Debug,
+)]
+#[serde(crate = "near_sdk::serde")]
+#[abi(json)]
+pub struct MerkleSecrets {
+    pub root: Base64VecU8,
+    pub parts: u32,
+    /// Keccak-256, as in Fusion+, if omitted
+    #[serde(default = "keccak256")]
+    pub hash_algorithm: HashAlgorithm,
+}
+
+fn keccak256() -> HashAlgorithm {
+    HashAlgorithm::Keccak256
+}
+
+/// Secret a fill of a Merkle-committed swap reveals: its index and the
+/// proof of `hash(index ++ hashlock)` against the root.
+#[derive(Serialize, Deserialize, NearSchema, Clone)]
+#[serde(crate = "near_sdk::serde")]
+#[abi(json)]
+pub struct FillSecret {
+    pub index: u32,
+    pub proof: Vec<Base64VecU8>,
+}
+
+impl MerkleSecrets {
+    pub fn assert_valid(&self) {
+        assert!(self.root.0.len() == 32, "Merkle root must be 32 bytes");
+        assert!(
+            self.parts > 0 && self.parts <= MAX_SECRET_PARTS,
+            "Invalid number of secret parts"
+        );
+    }
+
+    /// Index of the secret unlocking a fill that brings the swap to
+    /// `filled_amount` of `total_amount`.
+    pub fn index_for(&self, filled_amount: u128, total_amount: u128) -> u32 {
+        if filled_amount >= total_amount {
+            self.parts
+        } else {
+            ((filled_amount - 1) * u128::from(self.parts) / total_amount) as u32
+        }
+    }
+
+    pub fn verify(&self, secret: &FillSecret, secret_hash: &[u8]) -> bool {
+        let mut leaf = [0u8; 32].to_vec();
+        leaf[28..].copy_from_slice(&secret.index.to_be_bytes());
+        leaf.extend_from_slice(secret_hash);
+
+        let node = secret
+            .proof
+            .iter()
+            .fold(self.hash_algorithm.hash(&leaf), |node, sibling| {
+                let pair = if node <= sibling.0 {
+                    [node, sibling.0.clone()]
+                } else {
+                    [sibling.0.clone(), node]
+                };
+                self.hash_algorithm.hash(&pair.concat())
+            });
+        node == self.root.0
+    }
+}
+
+/// Check the secret of a fill bringing `swap` to `filled_amount` and return
+/// its index and the algorithm its hashlock is under. Without secrets the resolver would pick its own hashlock, so
+/// swaps migrated from before they were required take no new fills.
+pub(crate) fn check_fill_secret(
+    swap: &PartialFillSwap,
+    hashlock: &[u8],
+    filled_amount: u128,
+    secret: FillSecret,
+) -> (u32, HashAlgorithm) {
+    let secrets = swap
+        .secrets
+        .as_ref()
+        .unwrap_or_else(|| env::panic_str("Swap has no Merkle secrets"));
+    assert!(secrets.verify(&secret, hashlock), "Invalid Merkle proof");
+    assert_eq!(
+        secret.index,
+        secrets.index_for(filled_amount, swap.total_amount.0),
+        "Secret index does not match the filled amount"
+    );
+    assert!(
+        swap.last_secret_index
+            .is_none_or(|last| secret.index > last),
+        "Secret index already used"
+    );
+    (secret.index, secrets.hash_algorithm)
+}
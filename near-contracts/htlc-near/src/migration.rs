@@ -0,0 +1,595 @@
+use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
+use near_sdk::collections::{LookupMap, LookupSet, UnorderedMap, UnorderedSet};
+use near_sdk::json_types::U128;
+use near_sdk::{env, near_bindgen, AccountId, Gas, GasWeight, NearToken, Promise, Timestamp};
+
+use crate::{
+    cleanup, safety_deposit, CrossChainHTLC, EscrowStatus, HTLCContract, HTLCNear, HTLCNearExt,
+    HashAlgorithm, HtlcEvent, PartialFill, PartialFillSwap, Role, StorageKey, SwapStatus,
+    Timelocks,
+};
+
+/// Storage key of the state version marker. State written before versioning
+/// has no marker and is read as `StateVersion::V1`.
+const STATE_VERSION_KEY: &[u8] = b"STATE_VERSION";
+
+/// Records `migrate_records` rewrites per call when no limit is given.
+const DEFAULT_MIGRATION_BATCH: u64 = 100;
+
+/// Layouts of the contract state, oldest first.
+#[derive(BorshDeserialize, BorshSerialize, Clone, Copy, PartialEq, Eq, Debug)]
+pub enum StateVersion {
+    /// Original deployment: escrow maps, owner and resolvers only
+    V1,
+    /// Status enums on escrow records; pause flag, nonce IDs, account and
+    /// swap indexes, NEP-145 storage, archive and record migration cursor;
+    /// claimable payouts, NFT escrows, safety deposits, roles and ownership
+    /// transfer, Dutch auctions and Merkle secrets on swaps, protocol fees
+    /// and trusted token contracts
+    V2,
+}
+
+pub const CURRENT_STATE_VERSION: StateVersion = StateVersion::V2;
+
+impl StateVersion {
+    pub fn read() -> Self {
+        env::storage_read(STATE_VERSION_KEY)
+            .map(|bytes| borsh::from_slice(&bytes).expect("Invalid state version"))
+            .unwrap_or(StateVersion::V1)
+    }
+
+    pub fn write(self) {
+        env::storage_write(STATE_VERSION_KEY, &borsh::to_vec(&self).unwrap());
+    }
+
+    pub fn number(self) -> u32 {
+        match self {
+            StateVersion::V1 => 1,
+            StateVersion::V2 => 2,
+        }
+    }
+}
+
+/// Status of a V1 escrow from its settlement flags.
+fn legacy_status(withdrawn: bool, refunded: bool) -> EscrowStatus {
+    if withdrawn {
+        EscrowStatus::Withdrawn
+    } else if refunded {
+        EscrowStatus::Refunded
+    } else {
+        EscrowStatus::Active
+    }
+}
+
+/// Escrow records `migrate_records` may not have rewritten from their V1
+/// layout yet.
+pub(crate) trait Record: BorshSerialize + BorshDeserialize {
+    type V1: BorshDeserialize + Into<Self>;
+}
+
+impl Record for HTLCContract {
+    type V1 = HTLCContractV1;
+}
+
+impl Record for CrossChainHTLC {
+    type V1 = CrossChainHTLCV1;
+}
+
+impl Record for PartialFillSwap {
+    type V1 = PartialFillSwapV1;
+}
+
+impl Record for PartialFill {
+    type V1 = PartialFillV1;
+}
+
+/// The V1 record in `raw`, or `None` if it is in the current layout
+/// already. A V1 record never decodes in the current layout: its settlement
+/// flags, or a swap's receiver, put the fields after them out of line.
+fn legacy<R: Record>(raw: &[u8]) -> Option<R::V1> {
+    R::try_from_slice(raw)
+        .is_err()
+        .then(|| R::V1::try_from_slice(raw).expect("Invalid legacy record"))
+}
+
+fn decode_record<R: Record>(raw: &[u8]) -> R {
+    R::try_from_slice(raw).unwrap_or_else(|_| {
+        R::V1::try_from_slice(raw)
+            .expect("Invalid legacy record")
+            .into()
+    })
+}
+
+/// Reads of escrow maps that decode the records `migrate_records` hasn't
+/// reached yet from their V1 layout, so views and settlement keep working
+/// while it runs.
+pub(crate) trait RecordMap<R> {
+    fn record(&self, id: &str) -> Option<R>;
+
+    /// Up to `take` records from position `skip` on.
+    fn records(&self, skip: usize, take: usize) -> impl Iterator<Item = (String, R)> + '_;
+}
+
+impl<R: Record> RecordMap<R> for UnorderedMap<String, R> {
+    fn record(&self, id: &str) -> Option<R> {
+        get_raw(self, id).map(|raw| decode_record(&raw))
+    }
+
+    fn records(&self, skip: usize, take: usize) -> impl Iterator<Item = (String, R)> + '_ {
+        let start = u64::try_from(skip).unwrap_or(u64::MAX).min(self.len());
+        let end = start.saturating_add(take as u64).min(self.len());
+        (start..end).map(|index| {
+            let (id, raw) = read_raw(self, index);
+            (id, decode_record(&raw))
+        })
+    }
+}
+
+#[derive(BorshDeserialize, BorshSerialize)]
+pub struct HTLCContractV1 {
+    pub sender: AccountId,
+    pub receiver: AccountId,
+    pub amount: U128,
+    pub hashlock: Vec<u8>,
+    pub timelock: Timestamp,
+    pub withdrawn: bool,
+    pub refunded: bool,
+    pub eth_address: String,
+}
+
+impl From<HTLCContractV1> for HTLCContract {
+    fn from(old: HTLCContractV1) -> Self {
+        Self {
+            sender: old.sender,
+            receiver: old.receiver,
+            amount: old.amount,
+            hashlock: old.hashlock,
+            timelock: old.timelock,
+            status: legacy_status(old.withdrawn, old.refunded),
+            eth_address: old.eth_address,
+            token: None,
+            hash_algorithm: HashAlgorithm::Sha256,
+            // Agreed without a fee
+            fee_bps: 0,
+        }
+    }
+}
+
+#[derive(BorshDeserialize, BorshSerialize)]
+pub struct CrossChainHTLCV1 {
+    pub sender: AccountId,
+    pub receiver: AccountId,
+    pub amount: U128,
+    pub hashlock: Vec<u8>,
+    pub timelock: Timestamp,
+    pub withdrawn: bool,
+    pub refunded: bool,
+    pub eth_address: String,
+    pub eth_tx_hash: Option<String>,
+}
+
+impl From<CrossChainHTLCV1> for CrossChainHTLC {
+    fn from(old: CrossChainHTLCV1) -> Self {
+        Self {
+            sender: old.sender,
+            receiver: old.receiver,
+            amount: old.amount,
+            hashlock: old.hashlock,
+            timelock: old.timelock,
+            status: legacy_status(old.withdrawn, old.refunded),
+            eth_address: old.eth_address,
+            eth_tx_hash: old.eth_tx_hash,
+            token: None,
+            hash_algorithm: HashAlgorithm::Sha256,
+            timelocks: Timelocks::single(old.timelock),
+            safety_deposit: U128(0),
+            safety_deposit_pending: false,
+            fee_bps: 0,
+        }
+    }
+}
+
+#[derive(BorshDeserialize, BorshSerialize)]
+pub struct PartialFillV1 {
+    pub fill_id: String,
+    pub parent_swap_id: String,
+    pub sender: AccountId,
+    pub receiver: AccountId,
+    pub fill_amount: U128,
+    pub hashlock: Vec<u8>,
+    pub timelock: Timestamp,
+    pub completed: bool,
+    pub refunded: bool,
+    pub eth_address: String,
+    pub eth_tx_hash: Option<String>,
+    pub created_at: Timestamp,
+}
+
+impl From<PartialFillV1> for PartialFill {
+    fn from(old: PartialFillV1) -> Self {
+        Self {
+            fill_id: old.fill_id,
+            parent_swap_id: old.parent_swap_id,
+            sender: old.sender,
+            receiver: old.receiver,
+            fill_amount: old.fill_amount,
+            hashlock: old.hashlock,
+            timelock: old.timelock,
+            status: legacy_status(old.completed, old.refunded),
+            eth_address: old.eth_address,
+            eth_tx_hash: old.eth_tx_hash,
+            created_at: old.created_at,
+            hash_algorithm: HashAlgorithm::Sha256,
+            min_destination_amount: None,
+            secret_index: None,
+            fee_bps: 0,
+        }
+    }
+}
+
+#[derive(BorshDeserialize, BorshSerialize)]
+pub struct PartialFillSwapV1 {
+    pub swap_id: String,
+    pub sender: AccountId,
+    pub receiver: AccountId,
+    pub total_amount: U128,
+    pub filled_amount: U128,
+    pub remaining_amount: U128,
+    pub eth_address: String,
+    pub timelock: Timestamp,
+    pub completed: bool,
+    pub created_at: Timestamp,
+    pub fill_count: u32,
+}
+
+impl From<PartialFillSwapV1> for PartialFillSwap {
+    fn from(old: PartialFillSwapV1) -> Self {
+        Self {
+            swap_id: old.swap_id,
+            sender: old.sender,
+            total_amount: old.total_amount,
+            filled_amount: old.filled_amount,
+            // Only fills were ever funded; the rest can't be claimed
+            remaining_amount: U128(0),
+            eth_address: old.eth_address,
+            timelock: old.timelock,
+            // Nothing was left to claim and V1 fills are counted below
+            status: SwapStatus::Settled,
+            created_at: old.created_at,
+            fill_count: old.fill_count,
+            auction: None,
+            secrets: None,
+            last_secret_index: None,
+            open_fill_count: 0,
+            fee_bps: 0,
+        }
+    }
+}
+
+#[derive(BorshDeserialize, BorshSerialize)]
+pub struct HTLCNearV1 {
+    pub contracts: UnorderedMap<String, HTLCContractV1>,
+    pub cross_chain_contracts: UnorderedMap<String, CrossChainHTLCV1>,
+    pub partial_fill_swaps: UnorderedMap<String, PartialFillSwapV1>,
+    pub partial_fills: UnorderedMap<String, PartialFillV1>,
+    pub owner: AccountId,
+    pub authorized_resolvers: UnorderedMap<AccountId, bool>,
+}
+
+/// Contract state as found in storage, in whichever layout it was written.
+/// Only read once per `migrate`, so the variant sizes don't matter.
+#[allow(clippy::large_enum_variant)]
+pub enum VersionedState {
+    V1(HTLCNearV1),
+    V2(HTLCNear),
+}
+
+impl VersionedState {
+    pub fn read() -> Self {
+        match StateVersion::read() {
+            StateVersion::V1 => VersionedState::V1(env::state_read().expect("No V1 state")),
+            StateVersion::V2 => VersionedState::V2(env::state_read().expect("No V2 state")),
+        }
+    }
+
+    /// Upgrade to the current layout. Records of an older layout still have
+    /// to be rewritten with `migrate_records`.
+    pub fn into_current(self) -> HTLCNear {
+        match self {
+            VersionedState::V1(old) => HTLCNear {
+                contracts: retype(old.contracts),
+                cross_chain_contracts: retype(old.cross_chain_contracts),
+                partial_fill_swaps: retype(old.partial_fill_swaps),
+                partial_fills: retype(old.partial_fills),
+                owner: old.owner,
+                authorized_resolvers: old.authorized_resolvers,
+                // Stay paused until every record is in the new layout
+                paused: true,
+                next_nonce: 0,
+                escrows_by_sender: LookupMap::new(StorageKey::EscrowsBySender),
+                escrows_by_receiver: LookupMap::new(StorageKey::EscrowsByReceiver),
+                fills_by_swap: LookupMap::new(StorageKey::FillsBySwap),
+                storage_accounts: LookupMap::new(StorageKey::StorageAccounts),
+                retention_period_ms: cleanup::DEFAULT_RETENTION_PERIOD_MS,
+                archived_escrows: LookupMap::new(StorageKey::ArchivedEscrows),
+                migration: Some(MigrationCursor::default()),
+                claimable_balances: LookupMap::new(StorageKey::ClaimableBalances),
+                nft_escrows: UnorderedMap::new(StorageKey::NftEscrows),
+                min_safety_deposit: U128(safety_deposit::DEFAULT_MIN_SAFETY_DEPOSIT),
+                permissionless: false,
+                role_members: LookupMap::new(StorageKey::RoleMembers),
+                pending_owner: None,
+                // No fee on new escrows until the fee manager sets one
+                fee_bps: 0,
+                fee_balances: UnorderedMap::new(StorageKey::FeeBalances),
+                trusted_token_contracts: LookupSet::new(StorageKey::TrustedTokenContracts),
+            },
+            VersionedState::V2(current) => current,
+        }
+    }
+}
+
+/// Reinterpret a map's handle with new key and value types. Only the
+/// collection's prefix and length are stored in the handle, so this keeps
+/// pointing at the same entries.
+fn retype<K, V, K2, V2>(map: UnorderedMap<K, V>) -> UnorderedMap<K2, V2>
+where
+    K: BorshSerialize + BorshDeserialize,
+    V: BorshSerialize + BorshDeserialize,
+{
+    borsh::from_slice(&borsh::to_vec(&map).unwrap()).unwrap()
+}
+
+/// Position of the V1 record rewrite in each escrow map.
+#[derive(BorshDeserialize, BorshSerialize, Clone, Default)]
+pub struct MigrationCursor {
+    pub contracts: u64,
+    pub cross_chain_contracts: u64,
+    pub partial_fill_swaps: u64,
+    pub partial_fills: u64,
+}
+
+impl HTLCNear {
+    /// Rewrite up to `budget` V1 records from `cursor` on, indexing each one.
+    /// Records already rewritten by `migrate_escrow` are skipped. Returns the
+    /// number of records processed.
+    fn migrate_record_batch(&mut self, cursor: &mut MigrationCursor, mut budget: u64) -> u64 {
+        let start = budget;
+
+        while budget > 0 && cursor.contracts < self.contracts.len() {
+            let (id, raw) = read_raw(&self.contracts, cursor.contracts);
+            if let Some(old) = legacy::<HTLCContract>(&raw) {
+                self.migrate_contract(&id, old);
+            }
+            cursor.contracts += 1;
+            budget -= 1;
+        }
+
+        while budget > 0 && cursor.cross_chain_contracts < self.cross_chain_contracts.len() {
+            let (id, raw) = read_raw(&self.cross_chain_contracts, cursor.cross_chain_contracts);
+            if let Some(old) = legacy::<CrossChainHTLC>(&raw) {
+                self.migrate_cross_chain_contract(&id, old);
+            }
+            cursor.cross_chain_contracts += 1;
+            budget -= 1;
+        }
+
+        while budget > 0 && cursor.partial_fill_swaps < self.partial_fill_swaps.len() {
+            let (id, raw) = read_raw(&self.partial_fill_swaps, cursor.partial_fill_swaps);
+            if let Some(old) = legacy::<PartialFillSwap>(&raw) {
+                self.migrate_swap(&id, old);
+            }
+            cursor.partial_fill_swaps += 1;
+            budget -= 1;
+        }
+
+        // Swaps are rewritten first
+        while budget > 0 && cursor.partial_fills < self.partial_fills.len() {
+            let (id, raw) = read_raw(&self.partial_fills, cursor.partial_fills);
+            if let Some(old) = legacy::<PartialFill>(&raw) {
+                self.migrate_fill(&id, old);
+            }
+            cursor.partial_fills += 1;
+            budget -= 1;
+        }
+
+        start - budget
+    }
+
+    /// Rewrite escrow `id` in the current layout and index it if
+    /// `migrate_records` hasn't reached it yet, so it can settle while the
+    /// migration runs. A fill's swap is rewritten before the fill.
+    pub(crate) fn migrate_escrow(&mut self, id: &String) {
+        if self.migration.is_none() {
+            return;
+        }
+        if let Some(old) = get_raw(&self.contracts, id).and_then(|raw| legacy::<HTLCContract>(&raw))
+        {
+            self.migrate_contract(id, old);
+        } else if let Some(old) =
+            get_raw(&self.cross_chain_contracts, id).and_then(|raw| legacy::<CrossChainHTLC>(&raw))
+        {
+            self.migrate_cross_chain_contract(id, old);
+        } else if let Some(old) =
+            get_raw(&self.partial_fills, id).and_then(|raw| legacy::<PartialFill>(&raw))
+        {
+            let swap_id = old.parent_swap_id.clone();
+            if let Some(old_swap) = get_raw(&self.partial_fill_swaps, &swap_id)
+                .and_then(|raw| legacy::<PartialFillSwap>(&raw))
+            {
+                self.migrate_swap(&swap_id, old_swap);
+            }
+            self.migrate_fill(id, old);
+        }
+    }
+
+    fn migrate_contract(&mut self, id: &String, old: HTLCContractV1) {
+        let contract = HTLCContract::from(old);
+        write_raw(&mut self.contracts, id, &contract);
+        self.index_escrow(id, &contract.sender, &contract.receiver);
+    }
+
+    fn migrate_cross_chain_contract(&mut self, id: &String, old: CrossChainHTLCV1) {
+        let contract = CrossChainHTLC::from(old);
+        write_raw(&mut self.cross_chain_contracts, id, &contract);
+        self.index_escrow(id, &contract.sender, &contract.receiver);
+    }
+
+    fn migrate_swap(&mut self, id: &String, old: PartialFillSwapV1) {
+        write_raw(
+            &mut self.partial_fill_swaps,
+            id,
+            &PartialFillSwap::from(old),
+        );
+        let swap_fills = self.swap_fills_or_new(id);
+        self.fills_by_swap.insert(id, &swap_fills);
+    }
+
+    /// Rewrite a V1 fill whose swap is in the current layout already.
+    fn migrate_fill(&mut self, id: &String, old: PartialFillV1) {
+        let fill = PartialFill::from(old);
+        write_raw(&mut self.partial_fills, id, &fill);
+        self.index_escrow(id, &fill.sender, &fill.receiver);
+        // An open fill keeps its swap open
+        if fill.status == EscrowStatus::Active {
+            if let Some(mut swap) = self.partial_fill_swaps.record(&fill.parent_swap_id) {
+                swap.open_fill_count += 1;
+                swap.status = SwapStatus::FullyAllocated;
+                self.partial_fill_swaps.insert(&fill.parent_swap_id, &swap);
+            }
+        }
+        let mut swap_fills = self.swap_fills_or_new(&fill.parent_swap_id);
+        swap_fills.insert(id);
+        self.fills_by_swap.insert(&fill.parent_swap_id, &swap_fills);
+    }
+
+    fn swap_fills_or_new(&self, swap_id: &String) -> UnorderedSet<String> {
+        self.fills_by_swap.get(swap_id).unwrap_or_else(|| {
+            UnorderedSet::new(StorageKey::SwapFills {
+                swap_hash: env::sha256(swap_id.as_bytes()),
+            })
+        })
+    }
+
+    fn is_migration_done(&self, cursor: &MigrationCursor) -> bool {
+        cursor.contracts >= self.contracts.len()
+            && cursor.cross_chain_contracts >= self.cross_chain_contracts.len()
+            && cursor.partial_fill_swaps >= self.partial_fill_swaps.len()
+            && cursor.partial_fills >= self.partial_fills.len()
+    }
+
+    pub(crate) fn assert_migrated(&self) {
+        assert!(
+            self.migration.is_none(),
+            "Record migration still in progress"
+        );
+    }
+}
+
+/// Overwrite the entry for `key` without decoding the value it replaces,
+/// which is still in the older layout.
+fn write_raw<V: BorshSerialize + BorshDeserialize>(
+    map: &mut UnorderedMap<String, V>,
+    key: &String,
+    value: &V,
+) {
+    map.insert_raw(&borsh::to_vec(key).unwrap(), &borsh::to_vec(value).unwrap());
+}
+
+/// Read the entry at `index` of `map` without decoding its value, which may
+/// still be in an older layout.
+fn read_raw<V: BorshSerialize + BorshDeserialize>(
+    map: &UnorderedMap<String, V>,
+    index: u64,
+) -> (String, Vec<u8>) {
+    let key = map.keys_as_vector().get(index).expect("Missing key");
+    let value = map
+        .values_as_vector()
+        .get_raw(index)
+        .expect("Missing value");
+    (key, value)
+}
+
+/// Read the entry for `key` without decoding its value. A map's handle
+/// starts with the prefix of its key index, which holds the position of
+/// each key's value.
+fn get_raw<V: BorshSerialize + BorshDeserialize>(
+    map: &UnorderedMap<String, V>,
+    key: &str,
+) -> Option<Vec<u8>> {
+    let handle = borsh::to_vec(map).unwrap();
+    let key_index_prefix = Vec::<u8>::deserialize(&mut handle.as_slice()).unwrap();
+    let index = env::storage_read(&[key_index_prefix, borsh::to_vec(key).unwrap()].concat())?;
+    let index = u64::from_le_bytes(index.try_into().expect("Invalid key index"));
+    Some(
+        map.values_as_vector()
+            .get_raw(index)
+            .expect("Missing value"),
+    )
+}
+
+#[near_bindgen]
+impl HTLCNear {
+    /// Upgrade the stored state to the current layout after deploying new
+    /// code. Older layouts leave the contract paused until
+    /// `migrate_records` has rewritten every record.
+    #[private]
+    #[init(ignore_state)]
+    pub fn migrate() -> Self {
+        let from = StateVersion::read();
+        let state = VersionedState::read().into_current();
+        CURRENT_STATE_VERSION.write();
+
+        HtlcEvent::StateMigrated {
+            from_version: from.number(),
+            to_version: CURRENT_STATE_VERSION.number(),
+        }
+        .emit();
+
+        state
+    }
+
+    /// Deploy the contract code passed as the raw call input and run
+    /// `migrate` on it (upgrader role).
+    pub fn upgrade(&mut self) -> Promise {
+        self.assert_role(Role::Upgrader);
+        let code = env::input().unwrap_or_else(|| env::panic_str("Missing contract code"));
+        Promise::new(env::current_account_id())
+            .deploy_contract(code)
+            .function_call_weight(
+                "migrate".to_string(),
+                vec![],
+                NearToken::from_yoctonear(0),
+                Gas::from_gas(0),
+                GasWeight(1),
+            )
+    }
+
+    /// Rewrite up to `limit` records left in an older layout by `migrate`
+    /// and add them to the account and swap indexes. Returns true once every
+    /// record is migrated (upgrader role).
+    pub fn migrate_records(&mut self, limit: Option<u64>) -> bool {
+        self.assert_role(Role::Upgrader);
+        let Some(mut cursor) = self.migration.take() else {
+            return true;
+        };
+
+        self.migrate_record_batch(&mut cursor, limit.unwrap_or(DEFAULT_MIGRATION_BATCH));
+
+        if self.is_migration_done(&cursor) {
+            true
+        } else {
+            self.migration = Some(cursor);
+            false
+        }
+    }
+
+    pub fn get_state_version(&self) -> u32 {
+        StateVersion::read().number()
+    }
+
+    /// Whether `migrate_records` still has records to rewrite
+    pub fn is_migration_pending(&self) -> bool {
+        self.migration.is_some()
+    }
+}
@@ -0,0 +1,317 @@
+use near_contract_standards::non_fungible_token::core::{ext_nft_core, NonFungibleTokenReceiver};
+use near_contract_standards::non_fungible_token::TokenId;
+use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
+use near_sdk::json_types::Base64VecU8;
+use near_sdk::serde::{Deserialize, Serialize};
+use near_sdk::{
+    env, near_bindgen, AccountId, Gas, NearToken, Promise, PromiseOrValue, PromiseResult, Timestamp,
+};
+
+use crate::storage;
+use crate::views::page_bounds;
+use crate::{
+    EscrowKind, EscrowStatus, HTLCNear, HTLCNearExt, HashAlgorithm, HtlcArgs, HtlcEvent,
+    NftHTLCView,
+};
+
+/// Gas attached to `nft_transfer` on the NFT contract.
+const GAS_FOR_NFT_TRANSFER: Gas = Gas::from_tgas(15);
+
+/// Gas reserved for `on_nft_transfer_complete`.
+const GAS_FOR_NFT_TRANSFER_CALLBACK: Gas = Gas::from_tgas(10);
+
+// NEP-171 token locked under a hashlock, settled like a CrossChainHTLC
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone)]
+#[serde(crate = "near_sdk::serde")]
+pub struct NftHTLC {
+    pub sender: AccountId,
+    pub receiver: AccountId,
+    pub nft_contract: AccountId,
+    pub token_id: TokenId,
+    pub hashlock: Vec<u8>,
+    pub timelock: Timestamp,
+    pub status: EscrowStatus,
+    pub eth_address: String,
+    pub eth_tx_hash: Option<String>,
+    // The transfer out of escrow failed; the token can be claimed again
+    pub transfer_failed: bool,
+    pub hash_algorithm: HashAlgorithm,
+}
+
+impl HTLCNear {
+    /// Send the escrowed token to `account_id` and check the result in
+    /// `on_nft_transfer_complete`.
+    fn transfer_nft(
+        &self,
+        contract_id: String,
+        escrow: &NftHTLC,
+        account_id: AccountId,
+    ) -> Promise {
+        ext_nft_core::ext(escrow.nft_contract.clone())
+            .with_attached_deposit(NearToken::from_yoctonear(1))
+            .with_static_gas(GAS_FOR_NFT_TRANSFER)
+            .nft_transfer(
+                account_id.clone(),
+                escrow.token_id.clone(),
+                None,
+                Some(contract_id.clone()),
+            )
+            .then(
+                Self::ext(env::current_account_id())
+                    .with_static_gas(GAS_FOR_NFT_TRANSFER_CALLBACK)
+                    .on_nft_transfer_complete(contract_id, account_id),
+            )
+    }
+}
+
+#[near_bindgen]
+impl NonFungibleTokenReceiver for HTLCNear {
+    /// Lock the transferred token in an HTLC described by `msg`, the JSON of
+    /// `HtlcArgs`. The NFT contract is the predecessor and is recorded on the
+    /// escrow; the previous owner must trust it with `trust_token_contract`.
+    /// Panics, and so returns the token, if `msg` is invalid or the escrow
+    /// can't be created.
+    fn nft_on_transfer(
+        &mut self,
+        sender_id: AccountId,
+        previous_owner_id: AccountId,
+        token_id: TokenId,
+        msg: String,
+    ) -> PromiseOrValue<bool> {
+        // `sender_id` may be an approved account; the escrow belongs to the owner
+        let _ = sender_id;
+        self.assert_not_paused();
+        let initial_storage = env::storage_usage();
+        let HtlcArgs {
+            receiver,
+            hashlock,
+            timelock,
+            eth_address,
+            hash_algorithm,
+            timelocks,
+        } = near_sdk::serde_json::from_str(&msg)
+            .unwrap_or_else(|_| env::panic_str("Invalid escrow message"));
+        self.assert_token_contract_trusted(&previous_owner_id);
+        assert!(
+            timelocks.is_none(),
+            "Timelock stages are only supported on cross-chain HTLCs"
+        );
+
+        assert!(
+            timelock > env::block_timestamp_ms(),
+            "Timelock must be in the future"
+        );
+        assert!(hashlock.0.len() == 32, "Hashlock must be 32 bytes");
+        assert!(!eth_address.is_empty(), "ETH address required");
+
+        let contract_id = self.next_escrow_id(EscrowKind::Nft);
+        assert!(
+            self.nft_escrows.get(&contract_id).is_none(),
+            "Escrow ID already in use"
+        );
+
+        let escrow = NftHTLC {
+            sender: previous_owner_id,
+            receiver,
+            nft_contract: env::predecessor_account_id(),
+            token_id,
+            hashlock: hashlock.0,
+            timelock,
+            status: EscrowStatus::Active,
+            eth_address,
+            eth_tx_hash: None,
+            transfer_failed: false,
+            hash_algorithm: hash_algorithm.unwrap_or_default(),
+        };
+
+        self.nft_escrows.insert(&contract_id, &escrow);
+        self.index_escrow(&contract_id, &escrow.sender, &escrow.receiver);
+        self.charge_storage(&escrow.sender, initial_storage);
+
+        HtlcEvent::NftHtlcCreated {
+            id: contract_id,
+            sender: escrow.sender,
+            receiver: escrow.receiver,
+            nft_contract: escrow.nft_contract,
+            token_id: escrow.token_id,
+            hashlock: hex::encode(&escrow.hashlock),
+            timelock: escrow.timelock,
+            eth_address: escrow.eth_address,
+            hash_algorithm: escrow.hash_algorithm,
+        }
+        .emit();
+
+        // Keep the token
+        PromiseOrValue::Value(false)
+    }
+}
+
+#[near_bindgen]
+impl HTLCNear {
+    /// Release an escrowed NFT to the receiver with the preimage
+    pub fn complete_nft_swap(
+        &mut self,
+        contract_id: String,
+        preimage: Base64VecU8,
+        eth_tx_hash: String,
+    ) {
+        let mut escrow = self
+            .nft_escrows
+            .get(&contract_id)
+            .expect("Contract does not exist");
+
+        escrow.status.transition(EscrowStatus::Withdrawn);
+        assert!(
+            env::predecessor_account_id() == escrow.receiver,
+            "Only receiver can withdraw"
+        );
+        assert!(
+            env::block_timestamp_ms() <= escrow.timelock,
+            "Timelock expired"
+        );
+
+        // Verify preimage
+        assert!(
+            escrow.hash_algorithm.matches(&preimage.0, &escrow.hashlock),
+            "Invalid preimage"
+        );
+
+        storage::assert_eth_tx_hash(&eth_tx_hash);
+        escrow.eth_tx_hash = Some(eth_tx_hash.clone());
+        self.nft_escrows.insert(&contract_id, &escrow);
+
+        self.transfer_nft(contract_id.clone(), &escrow, escrow.receiver.clone());
+
+        HtlcEvent::NftSwapCompleted {
+            id: contract_id,
+            sender: escrow.sender,
+            receiver: escrow.receiver,
+            nft_contract: escrow.nft_contract,
+            token_id: escrow.token_id,
+            status: escrow.status,
+            hashlock: hex::encode(&escrow.hashlock),
+            preimage: hex::encode(&preimage.0),
+            eth_address: escrow.eth_address,
+            eth_tx_hash,
+        }
+        .emit();
+    }
+
+    /// Return an escrowed NFT to the sender after the timelock
+    pub fn refund_nft(&mut self, contract_id: String) {
+        let mut escrow = self
+            .nft_escrows
+            .get(&contract_id)
+            .expect("Contract does not exist");
+
+        escrow.status.transition(EscrowStatus::Refunded);
+        assert!(
+            env::predecessor_account_id() == escrow.sender,
+            "Only sender can refund"
+        );
+        assert!(
+            env::block_timestamp_ms() > escrow.timelock,
+            "Timelock not expired"
+        );
+
+        self.nft_escrows.insert(&contract_id, &escrow);
+
+        self.transfer_nft(contract_id.clone(), &escrow, escrow.sender.clone());
+
+        HtlcEvent::NftHtlcRefunded {
+            id: contract_id,
+            sender: escrow.sender,
+            receiver: escrow.receiver,
+            nft_contract: escrow.nft_contract,
+            token_id: escrow.token_id,
+            status: escrow.status,
+            hashlock: hex::encode(&escrow.hashlock),
+            timelock: escrow.timelock,
+            eth_address: escrow.eth_address,
+        }
+        .emit();
+    }
+
+    /// Result of an `nft_transfer` out of escrow. A failed transfer leaves the
+    /// escrow settled and lets the payee retry with `claim_nft`.
+    #[private]
+    pub fn on_nft_transfer_complete(&mut self, contract_id: String, account_id: AccountId) -> bool {
+        let succeeded = matches!(env::promise_result(0), PromiseResult::Successful(_));
+        let mut escrow = self
+            .nft_escrows
+            .get(&contract_id)
+            .expect("Contract does not exist");
+        escrow.transfer_failed = !succeeded;
+        self.nft_escrows.insert(&contract_id, &escrow);
+
+        if succeeded {
+            HtlcEvent::NftPayoutCompleted {
+                escrow_id: contract_id,
+                account_id,
+                nft_contract: escrow.nft_contract,
+                token_id: escrow.token_id,
+            }
+            .emit();
+        } else {
+            HtlcEvent::NftPayoutFailed {
+                escrow_id: contract_id,
+                account_id,
+                nft_contract: escrow.nft_contract,
+                token_id: escrow.token_id,
+            }
+            .emit();
+        }
+        succeeded
+    }
+
+    /// Retry the transfer of a settled NFT escrow whose payout failed. Only
+    /// the receiver of a withdrawn or the sender of a refunded escrow.
+    pub fn claim_nft(&mut self, contract_id: String) {
+        let mut escrow = self
+            .nft_escrows
+            .get(&contract_id)
+            .expect("Contract does not exist");
+        assert!(escrow.transfer_failed, "Nothing to claim");
+
+        let payee = if escrow.status == EscrowStatus::Withdrawn {
+            escrow.receiver.clone()
+        } else {
+            escrow.sender.clone()
+        };
+        assert!(
+            env::predecessor_account_id() == payee,
+            "Only the payee can claim"
+        );
+
+        escrow.transfer_failed = false;
+        self.nft_escrows.insert(&contract_id, &escrow);
+        self.transfer_nft(contract_id, &escrow, payee);
+    }
+
+    pub fn get_nft_escrow(&self, contract_id: String) -> Option<NftHTLCView> {
+        self.nft_escrows
+            .get(&contract_id)
+            .map(|escrow| NftHTLCView::new(contract_id, escrow))
+    }
+
+    pub fn get_nft_escrow_count(&self) -> u64 {
+        self.nft_escrows.len()
+    }
+
+    /// List NFT escrows, paginated and filtered like `get_all_contracts`
+    pub fn get_all_nft_escrows(
+        &self,
+        from_index: Option<u64>,
+        limit: Option<u64>,
+        status: Option<EscrowStatus>,
+    ) -> Vec<NftHTLCView> {
+        let (skip, take) = page_bounds(from_index, limit);
+        self.nft_escrows
+            .iter()
+            .skip(skip)
+            .take(take)
+            .map(|(id, escrow)| NftHTLCView::new(id, escrow))
+            .filter(|view| status.is_none_or(|status| view.status == status))
+            .collect()
+    }
+}
@@ -0,0 +1,99 @@
+use near_contract_standards::fungible_token::core::ext_ft_core;
+use near_sdk::json_types::U128;
+use near_sdk::{env, near_bindgen, AccountId, Gas, NearToken, Promise, PromiseResult};
+
+use crate::{HTLCNear, HTLCNearExt, HtlcEvent};
+
+/// Gas reserved for `on_payout_complete`.
+const GAS_FOR_PAYOUT_CALLBACK: Gas = Gas::from_tgas(10);
+
+/// Gas attached to `ft_transfer` on the token contract.
+const GAS_FOR_FT_TRANSFER: Gas = Gas::from_tgas(10);
+
+impl HTLCNear {
+    /// Transfer `amount` of NEAR, or of the NEP-141 `token`, to `account_id`
+    /// and check the result in `on_payout_complete`. `escrow_id` is the
+    /// settled escrow or swap, or `None` when paying out a claimable balance.
+    pub(crate) fn pay_out(
+        &self,
+        escrow_id: Option<String>,
+        account_id: AccountId,
+        amount: U128,
+        token: Option<AccountId>,
+    ) -> Promise {
+        let transfer = match &token {
+            Some(token) => ext_ft_core::ext(token.clone())
+                .with_attached_deposit(NearToken::from_yoctonear(1))
+                .with_static_gas(GAS_FOR_FT_TRANSFER)
+                .ft_transfer(account_id.clone(), amount, escrow_id.clone()),
+            None => Promise::new(account_id.clone()).transfer(NearToken::from_yoctonear(amount.0)),
+        };
+        transfer.then(
+            Self::ext(env::current_account_id())
+                .with_static_gas(GAS_FOR_PAYOUT_CALLBACK)
+                .on_payout_complete(escrow_id, account_id, amount, token),
+        )
+    }
+
+    fn claimable_balance(&self, account_id: &AccountId, token: &Option<AccountId>) -> U128 {
+        self.claimable_balances
+            .get(&(account_id.clone(), token.clone()))
+            .unwrap_or(U128(0))
+    }
+}
+
+#[near_bindgen]
+impl HTLCNear {
+    /// Result of a payout transfer. A failed transfer leaves the escrow
+    /// settled and credits the amount to the account's claimable balance.
+    #[private]
+    pub fn on_payout_complete(
+        &mut self,
+        escrow_id: Option<String>,
+        account_id: AccountId,
+        amount: U128,
+        token: Option<AccountId>,
+    ) -> bool {
+        let succeeded = matches!(env::promise_result(0), PromiseResult::Successful(_));
+        if succeeded {
+            HtlcEvent::PayoutCompleted {
+                escrow_id,
+                account_id,
+                amount,
+                token,
+            }
+            .emit();
+        } else {
+            let balance = self.claimable_balance(&account_id, &token);
+            self.claimable_balances.insert(
+                &(account_id.clone(), token.clone()),
+                &U128(balance.0 + amount.0),
+            );
+            HtlcEvent::PayoutFailed {
+                escrow_id,
+                account_id,
+                amount,
+                token,
+            }
+            .emit();
+        }
+        succeeded
+    }
+
+    /// Retry every failed payout of `token` (NEAR if `None`) owed to the
+    /// caller in one transfer.
+    pub fn claim_payout(&mut self, token: Option<AccountId>) -> U128 {
+        let account_id = env::predecessor_account_id();
+        let amount = self
+            .claimable_balances
+            .remove(&(account_id.clone(), token.clone()))
+            .unwrap_or_else(|| env::panic_str("Nothing to claim"));
+
+        self.pay_out(None, account_id, amount, token);
+        amount
+    }
+
+    pub fn get_claimable_balance(&self, account_id: AccountId, token: Option<AccountId>) -> U128 {
+        self.claimable_balance(&account_id, &token)
+    }
+}
@@ -0,0 +1,145 @@
+use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
+use near_sdk::collections::UnorderedSet;
+use near_sdk::serde::{Deserialize, Serialize};
+use near_sdk::{env, near_bindgen, AccountId, NearSchema};
+
+use crate::views::page_bounds;
+use crate::{HTLCNear, HTLCNearExt, HtlcEvent, StorageKey};
+
+/// Admin permissions the owner can hand out. The owner holds every role.
+#[derive(
+    BorshDeserialize,
+    BorshSerialize,
+    Serialize,
+    Deserialize,
+    NearSchema,
+    Clone,
+    Copy,
+    PartialEq,
+    Eq,
+    Debug,
+)]
+#[serde(crate = "near_sdk::serde", rename_all = "snake_case")]
+#[abi(json)]
+pub enum Role {
+    /// Pause and unpause the contract
+    Pauser,
+    /// Authorize and revoke resolvers and set their terms
+    ResolverManager,
+    /// Set protocol fees and withdraw them
+    FeeManager,
+    /// Deploy new code and migrate the state
+    Upgrader,
+}
+
+impl HTLCNear {
+    pub(crate) fn assert_owner(&self) {
+        assert_eq!(env::predecessor_account_id(), self.owner, "Only owner");
+    }
+
+    /// Fail unless the predecessor is the owner or holds `role`.
+    pub(crate) fn assert_role(&self, role: Role) {
+        assert!(
+            self.has_role(role, env::predecessor_account_id()),
+            "Missing role {:?}",
+            role
+        );
+    }
+
+    fn role_members(&self, role: Role) -> UnorderedSet<AccountId> {
+        self.role_members
+            .get(&role)
+            .unwrap_or_else(|| UnorderedSet::new(StorageKey::RoleAccounts { role }))
+    }
+}
+
+#[near_bindgen]
+impl HTLCNear {
+    /// Give `account_id` the `role` (owner only)
+    pub fn grant_role(&mut self, role: Role, account_id: AccountId) {
+        self.assert_owner();
+        let mut members = self.role_members(role);
+        assert!(members.insert(&account_id), "Role already granted");
+        self.role_members.insert(&role, &members);
+
+        HtlcEvent::RoleGranted {
+            role,
+            account_id,
+            by: env::predecessor_account_id(),
+        }
+        .emit();
+    }
+
+    /// Take the `role` from `account_id` (owner only)
+    pub fn revoke_role(&mut self, role: Role, account_id: AccountId) {
+        self.assert_owner();
+        let mut members = self.role_members(role);
+        assert!(members.remove(&account_id), "Role not granted");
+        self.role_members.insert(&role, &members);
+
+        HtlcEvent::RoleRevoked {
+            role,
+            account_id,
+            by: env::predecessor_account_id(),
+        }
+        .emit();
+    }
+
+    pub fn has_role(&self, role: Role, account_id: AccountId) -> bool {
+        account_id == self.owner
+            || self
+                .role_members
+                .get(&role)
+                .is_some_and(|members| members.contains(&account_id))
+    }
+
+    /// List the accounts granted `role`, paginated. The owner is not listed.
+    pub fn get_role_members(
+        &self,
+        role: Role,
+        from_index: Option<u64>,
+        limit: Option<u64>,
+    ) -> Vec<AccountId> {
+        let (skip, take) = page_bounds(from_index, limit);
+        self.role_members(role)
+            .iter()
+            .skip(skip)
+            .take(take)
+            .collect()
+    }
+
+    /// Offer ownership to `new_owner`, who takes it with `accept_ownership`.
+    /// A later proposal replaces this one (owner only).
+    pub fn propose_owner(&mut self, new_owner: AccountId) {
+        self.assert_owner();
+        self.pending_owner = Some(new_owner.clone());
+
+        HtlcEvent::OwnershipProposed {
+            owner: self.owner.clone(),
+            pending_owner: new_owner,
+        }
+        .emit();
+    }
+
+    /// Take over ownership offered by `propose_owner`
+    pub fn accept_ownership(&mut self) {
+        let new_owner = env::predecessor_account_id();
+        assert_eq!(
+            self.pending_owner.as_ref(),
+            Some(&new_owner),
+            "Only the proposed owner can accept"
+        );
+        self.pending_owner = None;
+        let previous_owner = std::mem::replace(&mut self.owner, new_owner.clone());
+
+        HtlcEvent::OwnershipTransferred {
+            previous_owner,
+            new_owner,
+        }
+        .emit();
+    }
+
+    pub fn get_pending_owner(&self) -> Option<AccountId> {
+        self.pending_owner.clone()
+    }
+}
@@ -0,0 +1,148 @@
+use near_sdk::json_types::U128;
+use near_sdk::{env, near_bindgen, AccountId};
+
+use crate::migration::RecordMap;
+use crate::{CrossChainHTLC, EscrowStatus, HTLCNear, HTLCNearExt, HtlcEvent, Role};
+
+/// Default smallest safety deposit a resolver puts behind each escrow: 0.1 NEAR.
+pub const DEFAULT_MIN_SAFETY_DEPOSIT: u128 = 100_000_000_000_000_000_000_000;
+
+impl HTLCNear {
+    /// Whether a cross-chain HTLC `sender` is creating has to carry a
+    /// safety deposit. Destination escrows always do.
+    pub(crate) fn requires_safety_deposit(&self, sender: &AccountId) -> bool {
+        self.is_authorized_resolver(sender.clone())
+    }
+
+    /// Check the safety deposit attached to a NEAR cross-chain HTLC.
+    pub(crate) fn assert_safety_deposit(&self, safety_deposit: U128) {
+        assert!(
+            safety_deposit.0 >= self.min_safety_deposit.0,
+            "Resolvers must attach the minimum safety deposit"
+        );
+    }
+
+    /// Hand the safety deposit back to the sender after a settlement in a
+    /// private stage.
+    pub(crate) fn return_safety_deposit(&self, contract_id: &str, contract: &CrossChainHTLC) {
+        if contract.safety_deposit.0 == 0 {
+            return;
+        }
+        self.pay_out(
+            Some(contract_id.to_string()),
+            contract.sender.clone(),
+            contract.safety_deposit,
+            None,
+        );
+        HtlcEvent::SafetyDepositReturned {
+            id: contract_id.to_string(),
+            resolver: contract.sender.clone(),
+            amount: contract.safety_deposit,
+        }
+        .emit();
+    }
+
+    /// Pay the safety deposit to `caller`, who settled the stalled escrow in
+    /// a public stage.
+    pub(crate) fn slash_safety_deposit(
+        &self,
+        contract_id: &str,
+        contract: &CrossChainHTLC,
+        caller: AccountId,
+    ) {
+        if contract.safety_deposit.0 == 0 {
+            return;
+        }
+        self.pay_out(
+            Some(contract_id.to_string()),
+            caller.clone(),
+            contract.safety_deposit,
+            None,
+        );
+        HtlcEvent::SafetyDepositSlashed {
+            id: contract_id.to_string(),
+            resolver: contract.sender.clone(),
+            caller,
+            amount: contract.safety_deposit,
+        }
+        .emit();
+    }
+}
+
+/// Split the attached NEAR of a cross-chain HTLC into the escrowed amount
+/// and its `safety_deposit`.
+pub(crate) fn split_attached_deposit(safety_deposit: Option<U128>) -> (U128, U128) {
+    let safety_deposit = safety_deposit.unwrap_or(U128(0));
+    let amount = env::attached_deposit()
+        .as_yoctonear()
+        .checked_sub(safety_deposit.0)
+        .unwrap_or_else(|| env::panic_str("Attached deposit must cover the safety deposit"));
+    (U128(amount), safety_deposit)
+}
+
+/// Check a resolver's token escrow has its safety deposit locked.
+pub(crate) fn assert_safety_deposit_locked(contract: &CrossChainHTLC) {
+    assert!(
+        !contract.safety_deposit_pending,
+        "Safety deposit not locked"
+    );
+}
+
+#[near_bindgen]
+impl HTLCNear {
+    /// Lock the attached NEAR as the safety deposit of a cross-chain HTLC
+    /// funded with tokens, whose transfer can't carry NEAR (sender only).
+    /// A resolver's token escrow can't settle until it is locked.
+    #[payable]
+    pub fn lock_safety_deposit(&mut self, contract_id: String) {
+        let mut contract = self
+            .cross_chain_contracts
+            .record(&contract_id)
+            .expect("Contract does not exist");
+
+        assert!(
+            env::predecessor_account_id() == contract.sender,
+            "Only sender can lock the safety deposit"
+        );
+        assert!(
+            contract.token.is_some(),
+            "NEAR escrows take their safety deposit on creation"
+        );
+        assert_eq!(
+            contract.status,
+            EscrowStatus::Active,
+            "Escrow already settled"
+        );
+        assert!(
+            contract.safety_deposit.0 == 0,
+            "Safety deposit already locked"
+        );
+        let amount = env::attached_deposit().as_yoctonear();
+        assert!(
+            amount >= self.min_safety_deposit.0,
+            "Safety deposit below the minimum"
+        );
+
+        contract.safety_deposit = U128(amount);
+        contract.safety_deposit_pending = false;
+        self.cross_chain_contracts.insert(&contract_id, &contract);
+
+        HtlcEvent::SafetyDepositLocked {
+            id: contract_id,
+            resolver: contract.sender,
+            amount: contract.safety_deposit,
+        }
+        .emit();
+    }
+
+    /// Set the smallest safety deposit resolvers put behind a cross-chain
+    /// HTLC, in yoctoNEAR (resolver manager role)
+    pub fn set_min_safety_deposit(&mut self, min_safety_deposit: U128) {
+        self.assert_role(Role::ResolverManager);
+        self.min_safety_deposit = min_safety_deposit;
+    }
+
+    pub fn get_min_safety_deposit(&self) -> U128 {
+        self.min_safety_deposit
+    }
+}
@@ -0,0 +1,105 @@
+use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
+use near_sdk::serde::{Deserialize, Serialize};
+use near_sdk::{env, NearSchema, Timestamp};
+
+/// Lifecycle of a single escrow or partial fill. `Expired` is never stored:
+/// views and events report it for an `Active` escrow past its timelock.
+#[derive(
+    BorshDeserialize,
+    BorshSerialize,
+    Serialize,
+    Deserialize,
+    NearSchema,
+    Clone,
+    Copy,
+    PartialEq,
+    Eq,
+    Debug,
+)]
+#[serde(crate = "near_sdk::serde", rename_all = "snake_case")]
+#[abi(json)]
+pub enum EscrowStatus {
+    Active,
+    Withdrawn,
+    Refunded,
+    Expired,
+}
+
+impl EscrowStatus {
+    /// Status as of the current block time.
+    pub fn at(self, timelock: Timestamp) -> Self {
+        if self == EscrowStatus::Active && env::block_timestamp_ms() > timelock {
+            EscrowStatus::Expired
+        } else {
+            self
+        }
+    }
+
+    /// Settle an active escrow as `Withdrawn` or `Refunded`.
+    pub fn transition(&mut self, next: EscrowStatus) {
+        match (*self, next) {
+            (EscrowStatus::Active, EscrowStatus::Withdrawn | EscrowStatus::Refunded) => {
+                *self = next
+            }
+            (EscrowStatus::Withdrawn, _) => env::panic_str("Already withdrawn"),
+            (EscrowStatus::Refunded, _) => env::panic_str("Already refunded"),
+            (from, to) => {
+                env::panic_str(&format!("Invalid escrow transition {:?} -> {:?}", from, to))
+            }
+        }
+    }
+}
+
+/// Lifecycle of a partial fill swap. `Expired` is never stored: views
+/// report it for an `Open` swap past its timelock, whose unclaimed balance
+/// the sender can take back.
+#[derive(
+    BorshDeserialize,
+    BorshSerialize,
+    Serialize,
+    Deserialize,
+    NearSchema,
+    Clone,
+    Copy,
+    PartialEq,
+    Eq,
+    Debug,
+)]
+#[serde(crate = "near_sdk::serde", rename_all = "snake_case")]
+#[abi(json)]
+pub enum SwapStatus {
+    /// Resolvers may claim fills against the locked balance
+    Open,
+    /// The whole balance is claimed by fills, some still unsettled
+    FullyAllocated,
+    /// Nothing left in the swap and every fill settled
+    Settled,
+    /// Cancelled by the sender; existing fills settle on their own
+    Cancelled,
+    Expired,
+}
+
+impl SwapStatus {
+    /// Status as of the current block time.
+    pub fn at(self, timelock: Timestamp) -> Self {
+        if self == SwapStatus::Open && env::block_timestamp_ms() > timelock {
+            SwapStatus::Expired
+        } else {
+            self
+        }
+    }
+
+    pub fn transition(&mut self, next: SwapStatus) {
+        let allowed = matches!(
+            (*self, next),
+            (SwapStatus::Open, SwapStatus::FullyAllocated)
+                | (SwapStatus::Open, SwapStatus::Cancelled)
+                | (
+                    SwapStatus::Open | SwapStatus::FullyAllocated,
+                    SwapStatus::Settled
+                )
+        );
+        assert!(allowed, "Invalid swap transition {:?} -> {:?}", self, next);
+        *self = next;
+    }
+}
@@ -0,0 +1,232 @@
+use near_contract_standards::storage_management::{
+    StorageBalance, StorageBalanceBounds, StorageManagement,
+};
+use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
+use near_sdk::{
+    assert_one_yocto, env, log, near_bindgen, AccountId, NearToken, Promise, StorageUsage,
+};
+
+use crate::{HTLCNear, HTLCNearExt};
+
+/// Bytes reserved for an account's own storage record: a 64 byte account ID
+/// key, the record itself and trie overhead.
+pub const STORAGE_ACCOUNT_BYTES: StorageUsage = 200;
+
+/// Longest `eth_tx_hash` a settlement may record: a `0x`-prefixed 32 byte
+/// hash. Settlements are not charged for storage, so the field is capped.
+pub const MAX_ETH_TX_HASH_LEN: usize = 66;
+
+pub(crate) fn assert_eth_tx_hash(eth_tx_hash: &str) {
+    assert!(
+        eth_tx_hash.len() <= MAX_ETH_TX_HASH_LEN,
+        "ETH transaction hash too long"
+    );
+}
+
+/// NEP-145 balance of one account. Escrows created by the account are paid
+/// from `deposit`; `used_bytes` excludes the account record itself.
+#[derive(BorshDeserialize, BorshSerialize, Clone)]
+pub struct StorageAccount {
+    pub deposit: NearToken,
+    pub used_bytes: StorageUsage,
+}
+
+impl StorageAccount {
+    fn locked(&self) -> NearToken {
+        env::storage_byte_cost().saturating_mul((STORAGE_ACCOUNT_BYTES + self.used_bytes).into())
+    }
+
+    fn available(&self) -> NearToken {
+        self.deposit.saturating_sub(self.locked())
+    }
+
+    fn to_balance(&self) -> StorageBalance {
+        StorageBalance {
+            total: self.deposit,
+            available: self.available(),
+        }
+    }
+}
+
+impl HTLCNear {
+    /// Charge the storage grown since `initial_usage` to `account_id`.
+    pub(crate) fn charge_storage(&mut self, account_id: &AccountId, initial_usage: StorageUsage) {
+        let used = env::storage_usage().saturating_sub(initial_usage);
+        let mut account = self.storage_accounts.get(account_id).unwrap_or_else(|| {
+            env::panic_str("Account has no storage balance, call storage_deposit")
+        });
+        account.used_bytes += used;
+        assert!(
+            account.deposit >= account.locked(),
+            "Insufficient storage balance, call storage_deposit"
+        );
+        self.storage_accounts.insert(account_id, &account);
+    }
+
+    /// Fail unless `account_id` trusts the predecessor, a token contract
+    /// calling back with `account_id` as the sender, to create its escrows.
+    pub(crate) fn assert_token_contract_trusted(&self, account_id: &AccountId) {
+        assert!(
+            self.trusted_token_contracts
+                .contains(&(account_id.clone(), env::predecessor_account_id())),
+            "Token contract not trusted by the sender, call trust_token_contract"
+        );
+    }
+
+    /// Credit `freed_bytes` of removed storage back to `account_id`. Escrows
+    /// created before storage accounting have no balance to credit.
+    pub(crate) fn release_storage(&mut self, account_id: &AccountId, freed_bytes: StorageUsage) {
+        if let Some(mut account) = self.storage_accounts.get(account_id) {
+            account.used_bytes = account.used_bytes.saturating_sub(freed_bytes);
+            self.storage_accounts.insert(account_id, &account);
+        }
+    }
+}
+
+#[near_bindgen]
+impl StorageManagement for HTLCNear {
+    #[payable]
+    fn storage_deposit(
+        &mut self,
+        account_id: Option<AccountId>,
+        registration_only: Option<bool>,
+    ) -> StorageBalance {
+        let amount = env::attached_deposit();
+        let account_id = account_id.unwrap_or_else(env::predecessor_account_id);
+        let min_balance = self.storage_balance_bounds().min;
+
+        let account = match self.storage_accounts.get(&account_id) {
+            Some(account) if registration_only.unwrap_or(false) => {
+                log!("The account is already registered, refunding the deposit");
+                if amount > NearToken::from_yoctonear(0) {
+                    Promise::new(env::predecessor_account_id()).transfer(amount);
+                }
+                account
+            }
+            Some(mut account) => {
+                account.deposit = account.deposit.saturating_add(amount);
+                account
+            }
+            None => {
+                assert!(
+                    amount >= min_balance,
+                    "The attached deposit is less than the minimum storage balance"
+                );
+                let deposit = if registration_only.unwrap_or(false) {
+                    let refund = amount.saturating_sub(min_balance);
+                    if refund > NearToken::from_yoctonear(0) {
+                        Promise::new(env::predecessor_account_id()).transfer(refund);
+                    }
+                    min_balance
+                } else {
+                    amount
+                };
+                StorageAccount {
+                    deposit,
+                    used_bytes: 0,
+                }
+            }
+        };
+
+        self.storage_accounts.insert(&account_id, &account);
+        account.to_balance()
+    }
+
+    #[payable]
+    fn storage_withdraw(&mut self, amount: Option<NearToken>) -> StorageBalance {
+        assert_one_yocto();
+        let account_id = env::predecessor_account_id();
+        let mut account = self
+            .storage_accounts
+            .get(&account_id)
+            .unwrap_or_else(|| env::panic_str("The account is not registered"));
+
+        let available = account.available();
+        let amount = amount.unwrap_or(available);
+        assert!(
+            amount <= available,
+            "The amount is greater than the available storage balance"
+        );
+
+        account.deposit = account.deposit.saturating_sub(amount);
+        self.storage_accounts.insert(&account_id, &account);
+        if amount > NearToken::from_yoctonear(0) {
+            Promise::new(account_id).transfer(amount);
+        }
+        account.to_balance()
+    }
+
+    /// Escrows are funds held for a counterparty, so `force` is not supported:
+    /// an account can only unregister once its escrows no longer use storage.
+    #[payable]
+    fn storage_unregister(&mut self, force: Option<bool>) -> bool {
+        assert_one_yocto();
+        assert!(
+            !force.unwrap_or(false),
+            "Forced unregistration is not supported"
+        );
+        let account_id = env::predecessor_account_id();
+        let Some(account) = self.storage_accounts.get(&account_id) else {
+            log!("The account {} is not registered", &account_id);
+            return false;
+        };
+        assert!(
+            account.used_bytes == 0,
+            "Can't unregister an account whose escrows still use storage"
+        );
+
+        self.storage_accounts.remove(&account_id);
+        Promise::new(account_id).transfer(account.deposit);
+        true
+    }
+
+    fn storage_balance_bounds(&self) -> StorageBalanceBounds {
+        StorageBalanceBounds {
+            min: env::storage_byte_cost().saturating_mul(STORAGE_ACCOUNT_BYTES.into()),
+            max: None,
+        }
+    }
+
+    fn storage_balance_of(&self, account_id: AccountId) -> Option<StorageBalance> {
+        self.storage_accounts
+            .get(&account_id)
+            .map(|account| account.to_balance())
+    }
+}
+
+#[near_bindgen]
+impl HTLCNear {
+    /// Let `contract_id` create escrows in the caller's name from its
+    /// transfer callbacks, charged to the caller's storage balance. Any
+    /// contract can claim any sender in a callback, so escrows are only
+    /// created for senders that trust the calling token contract.
+    #[payable]
+    pub fn trust_token_contract(&mut self, contract_id: AccountId) {
+        assert_one_yocto();
+        let account_id = env::predecessor_account_id();
+        let initial_storage = env::storage_usage();
+        assert!(
+            self.trusted_token_contracts
+                .insert(&(account_id.clone(), contract_id)),
+            "Token contract already trusted"
+        );
+        self.charge_storage(&account_id, initial_storage);
+    }
+
+    pub fn distrust_token_contract(&mut self, contract_id: AccountId) {
+        let account_id = env::predecessor_account_id();
+        let initial_storage = env::storage_usage();
+        assert!(
+            self.trusted_token_contracts
+                .remove(&(account_id.clone(), contract_id)),
+            "Token contract not trusted"
+        );
+        let freed_bytes = initial_storage.saturating_sub(env::storage_usage());
+        self.release_storage(&account_id, freed_bytes);
+    }
+
+    pub fn is_token_contract_trusted(&self, account_id: AccountId, contract_id: AccountId) -> bool {
+        self.trusted_token_contracts
+            .contains(&(account_id, contract_id))
+    }
+}
@@ -0,0 +1,85 @@
+use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
+use near_sdk::serde::{Deserialize, Serialize};
+use near_sdk::{env, NearSchema, Timestamp};
+
+/// Fusion+ stage deadlines of a cross-chain HTLC, in milliseconds. Each
+/// field is the last moment of its stage, as in `TronFusionBridge.sol`:
+/// the receiver alone may withdraw until `withdrawal`, anyone holding the
+/// preimage until `public_withdrawal`, the sender alone may cancel until
+/// `cancellation` and anyone after that.
+#[derive(
+    BorshDeserialize,
+    BorshSerialize,
+    Serialize,
+    Deserialize,
+    NearSchema,
+    Clone,
+    Copy,
+    PartialEq,
+    Eq,
+    Debug,
+)]
+#[serde(crate = "near_sdk::serde")]
+#[abi(json)]
+pub struct Timelocks {
+    pub withdrawal: Timestamp,
+    pub public_withdrawal: Timestamp,
+    pub cancellation: Timestamp,
+}
+
+impl Timelocks {
+    /// A single deadline: the receiver may withdraw until `timelock`, after
+    /// which only the sender may cancel. There are no public windows, so
+    /// `cancellation` never ends.
+    pub fn single(timelock: Timestamp) -> Self {
+        Self {
+            withdrawal: timelock,
+            public_withdrawal: timelock,
+            cancellation: Timestamp::MAX,
+        }
+    }
+
+    pub fn assert_valid(&self) {
+        assert!(
+            self.withdrawal <= self.public_withdrawal
+                && self.public_withdrawal <= self.cancellation,
+            "Timelock stages must be in order"
+        );
+    }
+
+    pub fn stage(&self) -> TimelockStage {
+        let now = env::block_timestamp_ms();
+        if now <= self.withdrawal {
+            TimelockStage::ExclusiveWithdrawal
+        } else if now <= self.public_withdrawal {
+            TimelockStage::PublicWithdrawal
+        } else if now <= self.cancellation {
+            TimelockStage::Cancellation
+        } else {
+            TimelockStage::PublicCancellation
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, NearSchema, Clone, Copy, PartialEq, Eq, Debug)]
+#[serde(crate = "near_sdk::serde", rename_all = "snake_case")]
+#[abi(json)]
+pub enum TimelockStage {
+    /// Only the receiver may withdraw
+    ExclusiveWithdrawal,
+    /// Anyone holding the preimage may withdraw to the receiver
+    PublicWithdrawal,
+    /// Only the sender may cancel
+    Cancellation,
+    /// Anyone may cancel back to the sender
+    PublicCancellation,
+}
+
+impl TimelockStage {
+    pub fn is_withdrawal(&self) -> bool {
+        matches!(
+            self,
+            TimelockStage::ExclusiveWithdrawal | TimelockStage::PublicWithdrawal
+        )
+    }
+}
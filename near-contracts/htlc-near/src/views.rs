@@ -0,0 +1,268 @@
+use near_sdk::json_types::U128;
+use near_sdk::serde::{Deserialize, Serialize};
+use near_sdk::{AccountId, NearSchema, Timestamp};
+
+use crate::{
+    CrossChainHTLC, DutchAuction, EscrowStatus, HTLCContract, HashAlgorithm, MerkleSecrets,
+    NftHTLC, PartialFill, PartialFillSwap, SwapStatus, Timelocks,
+};
+
+// JSON views returned by the contract. Hashlocks are hex encoded and
+// amounts are yoctoNEAR strings, or token units for NEP-141 escrows,
+// matching the event payloads.
+
+pub(crate) const DEFAULT_PAGE_LIMIT: u64 = 50;
+pub(crate) const MAX_PAGE_LIMIT: u64 = 100;
+
+/// Turn `from_index` / `limit` view arguments into `(skip, take)` counts.
+/// `limit` defaults to `DEFAULT_PAGE_LIMIT` and is capped at `MAX_PAGE_LIMIT`.
+/// An index past `usize` on wasm32 saturates, giving an empty page.
+pub(crate) fn page_bounds(from_index: Option<u64>, limit: Option<u64>) -> (usize, usize) {
+    let skip = usize::try_from(from_index.unwrap_or(0)).unwrap_or(usize::MAX);
+    let take = limit.unwrap_or(DEFAULT_PAGE_LIMIT).min(MAX_PAGE_LIMIT);
+    (skip, usize::try_from(take).unwrap_or(usize::MAX))
+}
+
+#[derive(Serialize, Deserialize, NearSchema, Clone)]
+#[serde(crate = "near_sdk::serde")]
+#[abi(json)]
+pub struct HTLCContractView {
+    pub id: String,
+    pub sender: AccountId,
+    pub receiver: AccountId,
+    pub amount: U128,
+    pub hashlock: String,
+    pub timelock: Timestamp,
+    pub status: EscrowStatus,
+    pub eth_address: String,
+    pub token: Option<AccountId>,
+    pub hash_algorithm: HashAlgorithm,
+    pub fee_bps: u16,
+}
+
+impl HTLCContractView {
+    pub fn new(id: String, contract: HTLCContract) -> Self {
+        Self {
+            status: contract.status.at(contract.timelock),
+            id,
+            sender: contract.sender,
+            receiver: contract.receiver,
+            amount: contract.amount,
+            hashlock: hex::encode(&contract.hashlock),
+            timelock: contract.timelock,
+            eth_address: contract.eth_address,
+            token: contract.token,
+            hash_algorithm: contract.hash_algorithm,
+            fee_bps: contract.fee_bps,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, NearSchema, Clone)]
+#[serde(crate = "near_sdk::serde")]
+#[abi(json)]
+pub struct CrossChainHTLCView {
+    pub id: String,
+    pub sender: AccountId,
+    pub receiver: AccountId,
+    pub amount: U128,
+    pub hashlock: String,
+    pub timelock: Timestamp,
+    pub status: EscrowStatus,
+    pub eth_address: String,
+    pub eth_tx_hash: Option<String>,
+    pub token: Option<AccountId>,
+    pub hash_algorithm: HashAlgorithm,
+    pub timelocks: Timelocks,
+    pub safety_deposit: U128,
+    pub safety_deposit_pending: bool,
+    pub fee_bps: u16,
+}
+
+impl CrossChainHTLCView {
+    pub fn new(id: String, contract: CrossChainHTLC) -> Self {
+        Self {
+            status: contract.status.at(contract.timelock),
+            id,
+            sender: contract.sender,
+            receiver: contract.receiver,
+            amount: contract.amount,
+            hashlock: hex::encode(&contract.hashlock),
+            timelock: contract.timelock,
+            eth_address: contract.eth_address,
+            eth_tx_hash: contract.eth_tx_hash,
+            token: contract.token,
+            hash_algorithm: contract.hash_algorithm,
+            timelocks: contract.timelocks,
+            safety_deposit: contract.safety_deposit,
+            safety_deposit_pending: contract.safety_deposit_pending,
+            fee_bps: contract.fee_bps,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, NearSchema, Clone)]
+#[serde(crate = "near_sdk::serde")]
+#[abi(json)]
+pub struct NftHTLCView {
+    pub id: String,
+    pub sender: AccountId,
+    pub receiver: AccountId,
+    pub nft_contract: AccountId,
+    pub token_id: String,
+    pub hashlock: String,
+    pub timelock: Timestamp,
+    pub status: EscrowStatus,
+    pub eth_address: String,
+    pub eth_tx_hash: Option<String>,
+    pub transfer_failed: bool,
+    pub hash_algorithm: HashAlgorithm,
+}
+
+impl NftHTLCView {
+    pub fn new(id: String, escrow: NftHTLC) -> Self {
+        Self {
+            status: escrow.status.at(escrow.timelock),
+            id,
+            sender: escrow.sender,
+            receiver: escrow.receiver,
+            nft_contract: escrow.nft_contract,
+            token_id: escrow.token_id,
+            hashlock: hex::encode(&escrow.hashlock),
+            timelock: escrow.timelock,
+            eth_address: escrow.eth_address,
+            eth_tx_hash: escrow.eth_tx_hash,
+            transfer_failed: escrow.transfer_failed,
+            hash_algorithm: escrow.hash_algorithm,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, NearSchema, Clone)]
+#[serde(crate = "near_sdk::serde")]
+#[abi(json)]
+pub struct PartialFillSwapView {
+    pub swap_id: String,
+    pub sender: AccountId,
+    pub total_amount: U128,
+    pub filled_amount: U128,
+    pub remaining_amount: U128,
+    pub eth_address: String,
+    pub timelock: Timestamp,
+    pub status: SwapStatus,
+    pub created_at: Timestamp,
+    pub fill_count: u32,
+    pub auction: Option<DutchAuction>,
+    pub secrets: Option<MerkleSecrets>,
+    pub last_secret_index: Option<u32>,
+    pub open_fill_count: u32,
+    pub fee_bps: u16,
+}
+
+impl From<PartialFillSwap> for PartialFillSwapView {
+    fn from(swap: PartialFillSwap) -> Self {
+        Self {
+            swap_id: swap.swap_id,
+            sender: swap.sender,
+            total_amount: swap.total_amount,
+            filled_amount: swap.filled_amount,
+            remaining_amount: swap.remaining_amount,
+            eth_address: swap.eth_address,
+            timelock: swap.timelock,
+            status: swap.status.at(swap.timelock),
+            created_at: swap.created_at,
+            fill_count: swap.fill_count,
+            auction: swap.auction,
+            secrets: swap.secrets,
+            last_secret_index: swap.last_secret_index,
+            open_fill_count: swap.open_fill_count,
+            fee_bps: swap.fee_bps,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, NearSchema, Clone)]
+#[serde(crate = "near_sdk::serde")]
+#[abi(json)]
+pub struct PartialFillView {
+    pub fill_id: String,
+    pub parent_swap_id: String,
+    pub sender: AccountId,
+    pub receiver: AccountId,
+    pub fill_amount: U128,
+    pub hashlock: String,
+    pub timelock: Timestamp,
+    pub status: EscrowStatus,
+    pub eth_address: String,
+    pub eth_tx_hash: Option<String>,
+    pub created_at: Timestamp,
+    pub hash_algorithm: HashAlgorithm,
+    pub min_destination_amount: Option<U128>,
+    pub secret_index: Option<u32>,
+    pub fee_bps: u16,
+}
+
+impl From<PartialFill> for PartialFillView {
+    fn from(fill: PartialFill) -> Self {
+        Self {
+            status: fill.status.at(fill.timelock),
+            fill_id: fill.fill_id,
+            parent_swap_id: fill.parent_swap_id,
+            sender: fill.sender,
+            receiver: fill.receiver,
+            fill_amount: fill.fill_amount,
+            hashlock: hex::encode(&fill.hashlock),
+            timelock: fill.timelock,
+            eth_address: fill.eth_address,
+            eth_tx_hash: fill.eth_tx_hash,
+            created_at: fill.created_at,
+            hash_algorithm: fill.hash_algorithm,
+            min_destination_amount: fill.min_destination_amount,
+            secret_index: fill.secret_index,
+            fee_bps: fill.fee_bps,
+        }
+    }
+}
+
+/// Any escrow kind, tagged with `"kind"` in JSON. Returned by the
+/// per-account views, which span several collections.
+#[derive(Serialize, Deserialize, NearSchema, Clone)]
+#[serde(crate = "near_sdk::serde", tag = "kind", rename_all = "snake_case")]
+#[abi(json)]
+pub enum EscrowView {
+    Htlc(HTLCContractView),
+    CrossChain(CrossChainHTLCView),
+    PartialFill(PartialFillView),
+    Nft(NftHTLCView),
+}
+
+#[derive(Serialize, Deserialize, NearSchema, Clone)]
+#[serde(crate = "near_sdk::serde")]
+#[abi(json)]
+pub struct SwapProgressView {
+    pub total_amount: U128,
+    pub filled_amount: U128,
+    pub remaining_amount: U128,
+    pub fill_count: u32,
+    pub status: SwapStatus,
+    pub fill_percentage: u32,
+}
+
+impl From<&PartialFillSwap> for SwapProgressView {
+    fn from(swap: &PartialFillSwap) -> Self {
+        let fill_percentage = if swap.total_amount.0 > 0 {
+            ((swap.filled_amount.0 * 100) / swap.total_amount.0) as u32
+        } else {
+            0
+        };
+
+        Self {
+            total_amount: swap.total_amount,
+            filled_amount: swap.filled_amount,
+            remaining_amount: swap.remaining_amount,
+            fill_count: swap.fill_count,
+            status: swap.status.at(swap.timelock),
+            fill_percentage,
+        }
+    }
+}